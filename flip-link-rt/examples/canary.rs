@@ -0,0 +1,56 @@
+#![no_main]
+#![no_std]
+
+use core::{
+    hint::black_box,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::{debug, hprintln};
+use lm3s6965 as _;
+use panic_semihosting as _;
+
+/// Statics the overflow must not touch
+static STATICS: [AtomicU32; 4] = [
+    AtomicU32::new(1),
+    AtomicU32::new(2),
+    AtomicU32::new(3),
+    AtomicU32::new(4),
+];
+
+#[entry]
+fn main() -> ! {
+    flip_link_rt::fill_canary();
+    assert!(flip_link_rt::check_canary());
+
+    let depth = recurse(0);
+    hprintln!("the canary tripped at a recursion depth of {}", depth);
+
+    let intact = STATICS
+        .iter()
+        .enumerate()
+        .all(|(index, value)| value.load(Ordering::Relaxed) == index as u32 + 1);
+    hprintln!("statics intact: {}", intact);
+
+    // exit QEMU
+    // NOTE do not run this on hardware; it can corrupt OpenOCD state
+    if intact {
+        debug::exit(debug::EXIT_SUCCESS);
+    } else {
+        debug::exit(debug::EXIT_FAILURE);
+    }
+
+    loop {}
+}
+
+/// Recurses until the stack overflows into the canary; returns the depth at which it did
+#[inline(never)]
+fn recurse(depth: u32) -> u32 {
+    // the frame is written before the canary is checked
+    let frame = black_box([depth; 16]);
+    if !flip_link_rt::check_canary() {
+        return depth;
+    }
+    frame[depth as usize % 16].max(recurse(depth + 1))
+}
@@ -0,0 +1,49 @@
+#![no_main]
+#![no_std]
+
+use core::hint::black_box;
+
+use cortex_m_rt::{entry, exception, ExceptionFrame};
+use cortex_m_semihosting::{debug, hprintln};
+use flip_link_rt::fault::{self, FaultCause, FaultStatus};
+use lm3s6965 as _;
+use panic_semihosting as _;
+
+#[entry]
+fn main() -> ! {
+    // SAFETY: nothing uses PSP yet
+    unsafe { flip_link_rt::use_fault_stack() };
+
+    recurse(0);
+
+    hprintln!("error: the stack did not overflow");
+    debug::exit(debug::EXIT_FAILURE);
+    loop {}
+}
+
+/// Recurses until the stack overflows
+#[inline(never)]
+fn recurse(depth: u32) -> u32 {
+    let frame = black_box([depth; 16]);
+    if black_box(true) {
+        frame[depth as usize % 16] + recurse(depth + 1)
+    } else {
+        0
+    }
+}
+
+#[exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+    let sp = frame as *const ExceptionFrame as usize;
+    let cause = fault::classify(sp, &FaultStatus::read());
+    hprintln!("HardFault on the fault stack: {:?}", cause);
+
+    // exit QEMU
+    // NOTE do not run this on hardware; it can corrupt OpenOCD state
+    match cause {
+        FaultCause::StackOverflow { .. } => debug::exit(debug::EXIT_SUCCESS),
+        _ => debug::exit(debug::EXIT_FAILURE),
+    }
+
+    loop {}
+}
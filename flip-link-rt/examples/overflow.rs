@@ -0,0 +1,70 @@
+#![no_main]
+#![no_std]
+
+use core::{arch::asm, hint::black_box};
+
+use cortex_m_rt::{entry, exception, ExceptionFrame};
+use cortex_m_semihosting::{debug, hprintln};
+use flip_link_rt::fault::{self, FaultCause, FaultStatus};
+use lm3s6965 as _;
+use panic_semihosting as _;
+
+/// Bytes at the top of the stack that stay with the main stack pointer, for the HardFault handler
+const HANDLER_STACK: usize = 1024;
+
+#[entry]
+fn main() -> ! {
+    // the HardFault handler needs a working stack; so keep the top of the stack for it, on the main
+    // stack pointer (MSP), and let `thread` overflow the rest, on the process stack pointer (PSP)
+    unsafe {
+        asm!(
+            "mrs {sp}, msp",
+            "sub {sp}, {sp}, #{handler_stack}",
+            "msr psp, {sp}",
+            // CONTROL.SPSEL = 1: use PSP in thread mode
+            "movs {sp}, #2",
+            "msr control, {sp}",
+            "isb",
+            "bl {thread}",
+            sp = out(reg) _,
+            handler_stack = const HANDLER_STACK,
+            thread = sym thread,
+            options(noreturn),
+        )
+    }
+}
+
+extern "C" fn thread() -> ! {
+    recurse(0);
+
+    hprintln!("error: the stack did not overflow");
+    debug::exit(debug::EXIT_FAILURE);
+    loop {}
+}
+
+/// Recurses until the stack overflows
+#[inline(never)]
+fn recurse(depth: u32) -> u32 {
+    let frame = black_box([depth; 16]);
+    if black_box(true) {
+        frame[depth as usize % 16] + recurse(depth + 1)
+    } else {
+        0
+    }
+}
+
+#[exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+    let sp = frame as *const ExceptionFrame as usize;
+    let cause = fault::classify(sp, &FaultStatus::read());
+    hprintln!("HardFault: {:?}", cause);
+
+    // exit QEMU
+    // NOTE do not run this on hardware; it can corrupt OpenOCD state
+    match cause {
+        FaultCause::StackOverflow { .. } => debug::exit(debug::EXIT_SUCCESS),
+        _ => debug::exit(debug::EXIT_FAILURE),
+    }
+
+    loop {}
+}
@@ -0,0 +1,63 @@
+#![no_main]
+#![no_std]
+
+use core::{mem::MaybeUninit, ptr};
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::{debug, hprintln};
+use flip_link_rt::PAINT_PATTERN;
+use lm3s6965 as _;
+use panic_semihosting as _;
+
+/// Words of painted memory to dump
+const DUMP_WORDS: usize = 8;
+
+/// Not initialized by `cortex-m-rt`: if painting the stack reached the statics, this would hold the
+/// paint pattern; QEMU starts with zeroed RAM
+#[link_section = ".uninit.PAINT_CHECK"]
+static mut UNINIT: MaybeUninit<[u32; 4]> = MaybeUninit::uninit();
+
+#[entry]
+fn main() -> ! {
+    // `cortex-m-rt`'s `paint-stack` feature painted the stack at reset; nothing here paints it
+    let used = flip_link_rt::high_water_mark();
+    let stack = flip_link_rt::stack_bounds();
+
+    assert!(flip_link_rt::is_flipped());
+    hprintln!(
+        "stack: {:#010x}..{:#010x} ({} bytes)",
+        stack.start,
+        stack.end,
+        stack.len()
+    );
+    hprintln!("high-water mark at reset: {} bytes", used);
+
+    // the bottom of the stack, and the top of the painted part
+    dump(stack.start);
+    dump(stack.end - used - DUMP_WORDS * 4);
+
+    // SAFETY: only read, as plain integers
+    let uninit = unsafe { ptr::addr_of!(UNINIT).cast::<[u32; 4]>().read_volatile() };
+    let statics_intact = !uninit.contains(&PAINT_PATTERN);
+    hprintln!("statics intact: {}", statics_intact);
+
+    // exit QEMU
+    // NOTE do not run this on hardware; it can corrupt OpenOCD state
+    if used < stack.len() && statics_intact {
+        debug::exit(debug::EXIT_SUCCESS);
+    } else {
+        debug::exit(debug::EXIT_FAILURE);
+    }
+
+    loop {}
+}
+
+/// Prints `DUMP_WORDS` words of memory, starting at `address`
+fn dump(address: usize) {
+    let mut words = [0; DUMP_WORDS];
+    for (index, word) in words.iter_mut().enumerate() {
+        // SAFETY: within the stack, below the stack pointer, and aligned
+        *word = unsafe { ptr::read_volatile((address as *const u32).add(index)) };
+    }
+    hprintln!("{:#010x}: {:08x?}", address, words);
+}
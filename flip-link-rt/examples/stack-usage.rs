@@ -0,0 +1,47 @@
+#![no_main]
+#![no_std]
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::{debug, hprintln};
+use lm3s6965 as _;
+use panic_semihosting as _;
+
+#[entry]
+fn main() -> ! {
+    // SAFETY: single core, and nothing else runs yet
+    unsafe { flip_link_rt::paint_stack() };
+
+    assert!(flip_link_rt::is_flipped());
+    let stack = flip_link_rt::stack_bounds();
+    hprintln!(
+        "stack: {:#010x}..{:#010x} ({} bytes)",
+        stack.start,
+        stack.end,
+        stack.len()
+    );
+    hprintln!("free now: {} bytes", flip_link_rt::stack_free_now());
+
+    let before = flip_link_rt::high_water_mark();
+    hprintln!("high-water mark: {before} bytes");
+    hprintln!("sum: {}", recurse(16));
+    let after = flip_link_rt::high_water_mark();
+    hprintln!("high-water mark after recursing: {after} bytes");
+    assert!(after > before);
+
+    // exit QEMU
+    // NOTE do not run this on hardware; it can corrupt OpenOCD state
+    debug::exit(debug::EXIT_SUCCESS);
+
+    loop {}
+}
+
+/// Uses some stack
+#[inline(never)]
+fn recurse(depth: u32) -> u32 {
+    let frame = core::hint::black_box([depth; 16]);
+    if depth == 0 {
+        0
+    } else {
+        frame[0] + recurse(depth - 1)
+    }
+}
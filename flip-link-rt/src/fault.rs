@@ -0,0 +1,240 @@
+//! Tells stack overflows apart from other faults
+//!
+//! With `flip-link` a stack overflow turns into an access below the bottom of RAM, which is a
+//! BusFault (or a MemManage fault, with an MPU guard band below the stack). Those usually escalate
+//! to a HardFault; this module helps the HardFault handler find out whether the fault was a stack
+//! overflow:
+//!
+//! ``` ignore
+//! #[exception]
+//! unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+//!     let sp = frame as *const ExceptionFrame as usize;
+//!     match fault::classify(sp, &FaultStatus::read()) {
+//!         FaultCause::StackOverflow { depth } => { /* .. */ }
+//!         _ => { /* .. */ }
+//!     }
+//! }
+//! ```
+//!
+//! The handler itself needs a working stack. If the faulting code ran on the main stack, the
+//! processor pushes the exception frame onto the same overflowed stack, and locks up instead of
+//! running the handler. Run the code that may overflow on the process stack (PSP), or give the
+//! handler a stack of its own.
+
+/// Configurable Fault Status Register: MMFSR, BFSR and UFSR
+#[cfg(target_arch = "arm")]
+const CFSR: *const u32 = 0xe000_ed28 as *const u32;
+/// MemManage Fault Address Register
+#[cfg(target_arch = "arm")]
+const MMFAR: *const u32 = 0xe000_ed34 as *const u32;
+/// BusFault Address Register
+#[cfg(target_arch = "arm")]
+const BFAR: *const u32 = 0xe000_ed38 as *const u32;
+
+/// MMFSR.MMARVALID: MMFAR holds the faulting address
+const MMARVALID: u32 = 1 << 7;
+/// BFSR.BFARVALID: BFAR holds the faulting address
+const BFARVALID: u32 = 1 << 15;
+
+/// How far below the stack pointer a faulting access may lie and still count as a stack access
+///
+/// A `PUSH` or `VPUSH` that faults halfway through leaves the stack pointer unchanged, so the
+/// faulting address can lie up to 128 bytes (`VPUSH {d0-d15}`) below it.
+const STACK_ACCESS_WINDOW: usize = 128;
+
+/// Contents of the fault status and address registers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FaultStatus {
+    /// Configurable Fault Status Register
+    pub cfsr: u32,
+    /// BusFault Address Register; valid if `BFSR.BFARVALID` is set
+    pub bfar: u32,
+    /// MemManage Fault Address Register; valid if `MMFSR.MMARVALID` is set
+    pub mmfar: u32,
+}
+
+impl FaultStatus {
+    /// Reads the registers of the System Control Block
+    ///
+    /// Only available on ARMv7-M and ARMv8-M.Main; ARMv6-M has no fault status registers.
+    #[cfg(target_arch = "arm")]
+    pub fn read() -> Self {
+        // SAFETY: the registers are always present on the cores that have them, and reading them
+        // has no side effects
+        unsafe {
+            Self {
+                cfsr: CFSR.read_volatile(),
+                bfar: BFAR.read_volatile(),
+                mmfar: MMFAR.read_volatile(),
+            }
+        }
+    }
+
+    /// The faulting address, if the processor recorded one
+    pub fn fault_address(&self) -> Option<usize> {
+        if self.cfsr & BFARVALID != 0 {
+            Some(self.bfar as usize)
+        } else if self.cfsr & MMARVALID != 0 {
+            Some(self.mmfar as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// What caused a fault
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultCause {
+    /// The stack grew `depth` bytes past its bottom
+    StackOverflow { depth: usize },
+    /// Any other BusFault
+    BusFault { address: Option<usize> },
+    /// Any other MemManage fault
+    MemManage { address: Option<usize> },
+    /// A UsageFault; `ufsr` holds the UFSR bits that say which one
+    UsageFault { ufsr: u16 },
+    /// No fault status bits are set, e.g. on ARMv6-M, or for a HardFault that is not an escalated
+    /// fault
+    Other,
+}
+
+/// Classifies a fault, given the stack pointer of the faulting context and the fault status
+///
+/// `sp` is the address of the exception frame, e.g. of `cortex-m-rt`'s `ExceptionFrame`; where the
+/// processor would have pushed it if pushing it failed.
+pub fn classify(sp: usize, status: &FaultStatus) -> FaultCause {
+    classify_below(crate::stack_bounds().start, sp, status)
+}
+
+/// [`classify`], for a stack whose bottom is at `bottom`
+fn classify_below(bottom: usize, sp: usize, status: &FaultStatus) -> FaultCause {
+    // the stack pointer itself went below the bottom of the stack
+    if sp < bottom {
+        return FaultCause::StackOverflow { depth: bottom - sp };
+    }
+
+    let address = status.fault_address();
+    if let Some(address) = address {
+        // an access right below the stack pointer, and below the bottom of the stack
+        if address < bottom && sp - address <= STACK_ACCESS_WINDOW {
+            return FaultCause::StackOverflow {
+                depth: bottom - address,
+            };
+        }
+    }
+
+    let cfsr = status.cfsr;
+    if cfsr & 0xff00 != 0 {
+        FaultCause::BusFault {
+            address: address.filter(|_| cfsr & BFARVALID != 0),
+        }
+    } else if cfsr & 0xff != 0 {
+        FaultCause::MemManage {
+            address: address.filter(|_| cfsr & MMARVALID != 0),
+        }
+    } else if cfsr >> 16 != 0 {
+        FaultCause::UsageFault {
+            ufsr: (cfsr >> 16) as u16,
+        }
+    } else {
+        FaultCause::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for the stack `flip-link` would define; only its bottom matters here
+    #[no_mangle]
+    #[allow(non_upper_case_globals)]
+    static _stack_end: [u8; 256] = [0; 256];
+    #[no_mangle]
+    #[allow(non_upper_case_globals)]
+    static _stack_start: u8 = 0;
+
+    const BOTTOM: usize = 0x2000_0000;
+
+    fn status(cfsr: u32, bfar: u32, mmfar: u32) -> FaultStatus {
+        FaultStatus { cfsr, bfar, mmfar }
+    }
+
+    #[test]
+    fn stack_pointer_below_the_stack() {
+        let no_status = status(0, 0, 0);
+        assert_eq!(
+            classify_below(BOTTOM, BOTTOM - 32, &no_status),
+            FaultCause::StackOverflow { depth: 32 }
+        );
+        assert_eq!(
+            classify_below(BOTTOM, BOTTOM, &no_status),
+            FaultCause::Other
+        );
+    }
+
+    #[test]
+    fn access_below_the_stack() {
+        // a `PUSH` that faults at the bottom of RAM, with the stack pointer still above it
+        let bus_fault = status(BFARVALID | 1 << 9, (BOTTOM - 4) as u32, 0);
+        assert_eq!(
+            classify_below(BOTTOM, BOTTOM + 8, &bus_fault),
+            FaultCause::StackOverflow { depth: 4 }
+        );
+        // the same for a MemManage fault on an MPU guard band
+        let mem_manage = status(MMARVALID | 1, 0, (BOTTOM - 8) as u32);
+        assert_eq!(
+            classify_below(BOTTOM, BOTTOM, &mem_manage),
+            FaultCause::StackOverflow { depth: 8 }
+        );
+        // too far below the stack pointer to be a stack access
+        let far = BOTTOM + STACK_ACCESS_WINDOW + 4;
+        assert_eq!(
+            classify_below(BOTTOM, far, &bus_fault),
+            FaultCause::BusFault {
+                address: Some(BOTTOM - 4)
+            }
+        );
+    }
+
+    #[test]
+    fn other_faults() {
+        // a precise BusFault in peripheral space
+        assert_eq!(
+            classify_below(
+                BOTTOM,
+                BOTTOM + 64,
+                &status(BFARVALID | 1 << 9, 0x4000_0000, 0)
+            ),
+            FaultCause::BusFault {
+                address: Some(0x4000_0000)
+            }
+        );
+        // an imprecise BusFault has no address
+        assert_eq!(
+            classify_below(BOTTOM, BOTTOM + 64, &status(1 << 10, 0, 0)),
+            FaultCause::BusFault { address: None }
+        );
+        assert_eq!(
+            classify_below(BOTTOM, BOTTOM + 64, &status(MMARVALID | 1 << 1, 0, 0x1000)),
+            FaultCause::MemManage {
+                address: Some(0x1000)
+            }
+        );
+        // UFSR.DIVBYZERO
+        assert_eq!(
+            classify_below(BOTTOM, BOTTOM + 64, &status(1 << 25, 0, 0)),
+            FaultCause::UsageFault { ufsr: 1 << 9 }
+        );
+    }
+
+    #[test]
+    fn classify_uses_the_bottom_of_the_stack() {
+        let bottom = crate::stack_bounds().start;
+        assert_eq!(bottom, _stack_end.as_ptr() as usize);
+        assert_eq!(
+            classify(bottom - 16, &status(0, 0, 0)),
+            FaultCause::StackOverflow { depth: 16 }
+        );
+        assert_eq!(classify(bottom + 16, &status(0, 0, 0)), FaultCause::Other);
+    }
+}
@@ -0,0 +1,253 @@
+//! Runtime stack usage of programs linked with [`flip-link`]
+//!
+//! `flip-link` places the stack at the bottom of RAM, below the statics, and defines the
+//! `_stack_start` (top) and `_stack_end` (bottom) symbols. This crate reads them, so it only works
+//! in programs that are linked with `flip-link`: with the usual layout `_stack_end` may not be
+//! defined at all, or the stack may share its space with the heap and the statics.
+//!
+//! [`flip-link`]: https://github.com/knurling-rs/flip-link
+//!
+//! ```no_run
+//! // as early as possible, e.g. first thing in `main`
+//! unsafe { flip_link_rt::paint_stack() };
+//!
+//! // ... later
+//! let used = flip_link_rt::high_water_mark();
+//! let size = flip_link_rt::stack_bounds().len();
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod fault;
+
+use core::{mem, ops::Range, ptr, sync::atomic::AtomicU8};
+
+/// Value [`paint_stack`] fills the free stack with; the same as `cortex-m-rt`'s `paint-stack`
+/// feature
+pub const PAINT_PATTERN: u32 = 0xcccc_cccc;
+
+extern "C" {
+    /// Top of the stack, where the stack pointer starts
+    static _stack_start: u8;
+    /// Bottom of the stack, where it overflows
+    static _stack_end: u8;
+}
+
+/// The stack, from its bottom (`start`) to its top (`end`)
+///
+/// The stack grows downwards, from `end` towards `start`.
+pub fn stack_bounds() -> Range<usize> {
+    // only the addresses of the symbols are used, never their contents
+    ptr::addr_of!(_stack_end) as usize..ptr::addr_of!(_stack_start) as usize
+}
+
+/// The stack reserved for the fault handler, from its bottom (`start`) to its top (`end`)
+#[cfg(feature = "fault-stack")]
+pub fn fault_stack_bounds() -> Range<usize> {
+    extern "C" {
+        static __flip_link_fault_stack_start: u8;
+        static __flip_link_fault_stack_end: u8;
+    }
+
+    ptr::addr_of!(__flip_link_fault_stack_end) as usize
+        ..ptr::addr_of!(__flip_link_fault_stack_start) as usize
+}
+
+/// Moves exception handlers to the fault stack
+///
+/// From now on thread mode runs on the process stack pointer (PSP), which takes over the current
+/// stack, and exception handlers run on the main stack pointer (MSP), which moves to the top of the
+/// fault stack. When the (main) stack overflows, the processor can then still push the exception
+/// frame, and run the HardFault handler.
+///
+/// # Safety
+///
+/// Must be called from privileged thread mode, before anything else uses PSP, e.g. an RTOS.
+#[cfg(all(feature = "fault-stack", target_arch = "arm"))]
+pub unsafe fn use_fault_stack() {
+    core::arch::asm!(
+        "mov {tmp}, sp",
+        "msr psp, {tmp}",
+        // CONTROL.SPSEL = 1: thread mode uses PSP, which now holds the same value as MSP
+        "mrs {tmp}, control",
+        "orr {tmp}, {tmp}, #2",
+        "msr control, {tmp}",
+        "isb",
+        "msr msp, {top}",
+        tmp = out(reg) _,
+        top = in(reg) fault_stack_bounds().end,
+        options(nomem, preserves_flags),
+    );
+}
+
+/// Programs the ARMv8-M MSPLIM register with the bottom of the stack
+///
+/// From then on, the processor raises a UsageFault (STKOF) as soon as the main stack pointer goes
+/// below the stack, even if the memory below it is valid. Call this as early as possible, e.g.
+/// first thing in `main`.
+///
+/// # Safety
+///
+/// Must be called in privileged mode, while the main stack pointer is above the stack limit.
+#[cfg(all(feature = "stack-limit", target_arch = "arm"))]
+pub unsafe fn set_stack_limit() {
+    extern "C" {
+        static _stack_limit: u8;
+    }
+
+    core::arch::asm!(
+        "msr msplim, {}",
+        in(reg) ptr::addr_of!(_stack_limit),
+        options(nomem, nostack, preserves_flags),
+    );
+}
+
+/// Value of the stack seal of ARMv8-M TrustZone secure stacks
+#[cfg(feature = "stack-seal")]
+pub const STACK_SEAL: u32 = 0xfef5_eda5;
+
+/// Writes the stack seal above the stack of a TrustZone secure image
+///
+/// An exception return that pops the seal faults, so non-secure code cannot make secure code
+/// return to a forged exception frame at the top of its stack.
+///
+/// # Safety
+///
+/// Must run in secure state.
+#[cfg(feature = "stack-seal")]
+pub unsafe fn seal_stack() {
+    extern "C" {
+        static mut __flip_link_stack_seal: [u32; 2];
+    }
+
+    ptr::addr_of_mut!(__flip_link_stack_seal).write_volatile([STACK_SEAL; 2]);
+}
+
+/// Value [`fill_canary`] fills the canary with
+#[cfg(feature = "canary")]
+pub const CANARY: u32 = 0xdead_c0de;
+
+/// The canary block at the bottom of the stack
+#[cfg(feature = "canary")]
+fn canary() -> *mut [u32] {
+    extern "C" {
+        static mut __flip_link_canary_start: u32;
+        static __flip_link_canary_end: u8;
+    }
+
+    let start = ptr::addr_of_mut!(__flip_link_canary_start);
+    let len =
+        (ptr::addr_of!(__flip_link_canary_end) as usize - start as usize) / mem::size_of::<u32>();
+    ptr::slice_from_raw_parts_mut(start, len)
+}
+
+/// Fills the canary block at the bottom of the stack with [`CANARY`]
+///
+/// Call this at reset, e.g. first thing in `main`; the stack does not reach the canary unless it
+/// overflows.
+#[cfg(feature = "canary")]
+pub fn fill_canary() {
+    let canary = canary();
+    for index in 0..canary.len() {
+        // SAFETY: flip-link reserves the canary block for this crate
+        unsafe { canary.cast::<u32>().add(index).write_volatile(CANARY) };
+    }
+}
+
+/// Is the canary still intact?
+///
+/// If not, the stack has overflowed into it. Call this periodically, e.g. from SysTick or the idle
+/// loop: on chips without a faulting boundary below the stack, this is how to detect overflows.
+#[cfg(feature = "canary")]
+pub fn check_canary() -> bool {
+    let canary = canary();
+    // SAFETY: flip-link reserves the canary block for this crate
+    (0..canary.len())
+        .all(|index| unsafe { canary.cast::<u32>().add(index).read_volatile() } == CANARY)
+}
+
+/// Is the layout flipped, with the statics above the stack?
+///
+/// Everything in this crate relies on that; this checks it against one of the crate's own statics.
+pub fn is_flipped() -> bool {
+    // interior mutability keeps it in `.bss`, in RAM; an immutable static would go to `.rodata`,
+    // in flash
+    static STATIC: AtomicU8 = AtomicU8::new(0);
+
+    let stack = stack_bounds();
+    !stack.is_empty() && ptr::addr_of!(STATIC) as usize >= stack.end
+}
+
+/// Bytes of stack left below the current stack pointer
+pub fn stack_free_now() -> usize {
+    stack_pointer().saturating_sub(stack_bounds().start)
+}
+
+/// Fills the stack below the current stack pointer with [`PAINT_PATTERN`]
+///
+/// [`high_water_mark`] then tells how deep the stack has grown since.
+///
+/// # Safety
+///
+/// Nothing else may use the stack below the current stack pointer while this runs, e.g. another
+/// core. Interrupt handlers that preempt this function are fine: they are done with their part of
+/// the stack before this function continues.
+#[inline(never)]
+pub unsafe fn paint_stack() {
+    let bottom = stack_bounds().start;
+    // this function's own frame lies above the stack pointer; everything below it is unused
+    let top = stack_pointer();
+
+    let mut word = align_up(bottom) as *mut u32;
+    while (word as usize) < top {
+        ptr::write_volatile(word, PAINT_PATTERN);
+        word = word.add(1);
+    }
+}
+
+/// Deepest the stack has grown since [`paint_stack`], in bytes
+///
+/// This is a lower bound: code that happened to write [`PAINT_PATTERN`] to the stack looks like it
+/// didn't write anything. If the result equals the size of the stack, the stack has overflowed, or
+/// was never painted.
+pub fn high_water_mark() -> usize {
+    let stack = stack_bounds();
+
+    let mut word = align_up(stack.start) as *const u32;
+    while (word as usize) < stack.end {
+        // SAFETY: the address lies within the stack, and is aligned
+        if unsafe { ptr::read_volatile(word) } != PAINT_PATTERN {
+            break;
+        }
+        // SAFETY: stays within the stack (or right at its end)
+        word = unsafe { word.add(1) };
+    }
+
+    stack.end - (word as usize).min(stack.end)
+}
+
+fn align_up(address: usize) -> usize {
+    address.next_multiple_of(mem::align_of::<u32>())
+}
+
+/// Current value of the stack pointer
+#[inline(always)]
+fn stack_pointer() -> usize {
+    let sp: usize;
+    // SAFETY: only reads the stack pointer
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        core::arch::asm!("mov {}, sp", out(reg) sp, options(nomem, nostack, preserves_flags))
+    };
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        core::arch::asm!("mv {}, sp", out(reg) sp, options(nomem, nostack, preserves_flags))
+    };
+    // anywhere else, the address of a local is close enough
+    #[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+    {
+        let local = 0u8;
+        sp = ptr::addr_of!(local) as usize;
+    }
+    sp
+}
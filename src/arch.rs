@@ -0,0 +1,324 @@
+use std::ops::Range;
+
+use object::{AddressSize, Architecture, Object as _, ObjectSection as _, ObjectSymbol as _};
+
+/// Peripheral space at the bottom of the MSP430 address space: the special function registers and
+/// the 8- and 16-bit peripherals
+///
+/// This holds on every family. The `F5xx`, `F6xx` and `FRxx` families have more peripherals, up to
+/// `0x1000`; there the `peripheral-space` option gives the whole range.
+const MSP430_PERIPHERALS: Range<u64> = 0x0000..0x0200;
+
+/// `Tag_CPU_arch` in the `aeabi` build attributes
+const TAG_CPU_ARCH: u64 = 6;
+/// `Tag_CPU_arch` values of the architectures with stack limit registers: ARMv8-M.baseline,
+/// ARMv8-M.mainline and ARMv8.1-M.mainline
+const CPU_ARCH_V8M: [u64; 3] = [16, 17, 21];
+/// Prefix of the symbols of Cortex-M Security Extensions entry functions, which only secure
+/// images have
+const CMSE_PREFIX: &str = "__acle_se_";
+
+/// Properties of the target architecture, as recorded in the ELF header of the linked program
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Arch {
+    architecture: Architecture,
+    /// ARMv8-M, which has the MSPLIM and PSPLIM stack limit registers
+    stack_limit: bool,
+    /// TrustZone secure image
+    secure: bool,
+}
+
+impl Arch {
+    pub fn new(architecture: Architecture) -> Self {
+        Self {
+            architecture,
+            stack_limit: false,
+            secure: false,
+        }
+    }
+
+    /// Reads the architecture, and on ARM the build attributes and the CMSE symbols, of `object`
+    ///
+    /// Build attributes that cannot be read are not an error; the program is then assumed to have
+    /// no stack limit registers, like a program without them.
+    pub fn from_object(object: &object::File<'_>) -> Self {
+        let mut arch = Self::new(object.architecture());
+        if !arch.is_arm() {
+            return arch;
+        }
+
+        if let Some(section) = object.section_by_name(".ARM.attributes") {
+            let cpu_arch = section
+                .data()
+                .map_err(|e| format!("could not read .ARM.attributes: {e}"))
+                .and_then(|attributes| {
+                    cpu_arch(attributes)
+                        .map_err(|e| format!("malformed .ARM.attributes section: {e}"))
+                });
+            match cpu_arch {
+                Ok(cpu_arch) => {
+                    log::debug!("Tag_CPU_arch = {cpu_arch:?}");
+                    arch.stack_limit =
+                        cpu_arch.is_some_and(|cpu_arch| CPU_ARCH_V8M.contains(&cpu_arch));
+                }
+                Err(e) => log::warn!("{e}; assuming no stack limit registers"),
+            }
+        }
+        arch.secure = arch.stack_limit
+            && object.symbols().any(|symbol| {
+                symbol
+                    .name()
+                    .is_ok_and(|name| name.starts_with(CMSE_PREFIX))
+            });
+
+        arch
+    }
+
+    /// ARMv8-M, optionally a TrustZone secure image
+    #[cfg(test)]
+    pub fn armv8m(secure: bool) -> Self {
+        Self {
+            architecture: Architecture::Arm,
+            stack_limit: true,
+            secure,
+        }
+    }
+
+    pub fn is_arm(self) -> bool {
+        self.architecture == Architecture::Arm
+    }
+
+    /// Does the architecture have stack limit registers (MSPLIM / PSPLIM)?
+    pub fn has_stack_limit(self) -> bool {
+        self.stack_limit
+    }
+
+    /// Is this a TrustZone secure image, whose stacks need to be sealed?
+    pub fn is_secure(self) -> bool {
+        self.secure
+    }
+
+    /// Stack Pointer alignment required by the architecture
+    pub fn sp_align(self) -> u64 {
+        match self.architecture {
+            // `msp430-rt` only keeps the stack pointer word-aligned
+            Architecture::Msp430 => 2,
+            Architecture::Riscv32 | Architecture::Riscv64 => 16,
+            // required by the ARM architecture; also the default for anything we don't know about
+            _ => 8,
+        }
+    }
+
+    /// Highest address of the address space
+    pub fn max_address(self) -> u64 {
+        match self.architecture.address_size() {
+            Some(AddressSize::U8) => u8::MAX.into(),
+            Some(AddressSize::U16) => u16::MAX.into(),
+            Some(AddressSize::U32) => u32::MAX.into(),
+            _ => u64::MAX,
+        }
+    }
+
+    /// Width of an address in bits
+    pub fn address_bits(self) -> u32 {
+        u64::BITS - self.max_address().leading_zeros()
+    }
+
+    /// Memory-mapped peripheral space that every chip of the architecture has
+    ///
+    /// A stack that overflows into peripheral space does not fault; it writes to peripheral
+    /// registers instead. Where peripherals live on ARM and RISC-V depends on the vendor, so there
+    /// the `peripheral-space` option has to give it.
+    pub fn peripheral_space(self) -> Option<Range<u64>> {
+        match self.architecture {
+            Architecture::Msp430 => Some(MSP430_PERIPHERALS),
+            _ => None,
+        }
+    }
+}
+
+/// Reads `Tag_CPU_arch` from the contents of an `.ARM.attributes` section
+///
+/// The section holds a format version (`A`), then subsections of vendor attributes, each with a
+/// length, a vendor name and sub-subsections that are lists of `(tag, value)` pairs.
+fn cpu_arch(mut data: &[u8]) -> Result<Option<u64>, String> {
+    let Some((b'A', rest)) = data.split_first() else {
+        return Err("unknown format version".to_string());
+    };
+    data = rest;
+
+    while !data.is_empty() {
+        let (subsection, rest) = split_length_prefixed(data, 0)?;
+        data = rest;
+
+        let vendor_end = subsection
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or("unterminated vendor name")?;
+        if &subsection[..vendor_end] != b"aeabi" {
+            continue;
+        }
+
+        let mut subsubsections = &subsection[vendor_end + 1..];
+        while !subsubsections.is_empty() {
+            let tag = subsubsections[0];
+            let (attributes, rest) = split_length_prefixed(subsubsections, 1)?;
+            subsubsections = rest;
+
+            // `Tag_File`; the other scopes (sections, symbols) don't matter for the whole program
+            if tag != 1 {
+                continue;
+            }
+
+            let mut attributes = attributes;
+            while !attributes.is_empty() {
+                let tag = read_uleb128(&mut attributes)?;
+                if tag == TAG_CPU_ARCH {
+                    return read_uleb128(&mut attributes).map(Some);
+                }
+
+                // `Tag_compatibility` has a number and a string; `Tag_CPU_raw_name`,
+                // `Tag_CPU_name` and `Tag_conformance` a string; the other tags a string if they
+                // are odd, and a number if they are even, from 32 upwards
+                let is_string = matches!(tag, 4 | 5 | 67) || (tag > 32 && tag % 2 == 1);
+                if tag == 32 {
+                    read_uleb128(&mut attributes)?;
+                }
+                if is_string || tag == 32 {
+                    let end = attributes
+                        .iter()
+                        .position(|&byte| byte == 0)
+                        .ok_or("unterminated string attribute")?;
+                    attributes = &attributes[end + 1..];
+                } else {
+                    read_uleb128(&mut attributes)?;
+                }
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Splits a (sub)section off `data`, whose length, a little-endian `u32`, follows `header` bytes
+///
+/// Returns the contents after the length, and the rest of `data`.
+fn split_length_prefixed(data: &[u8], header: usize) -> Result<(&[u8], &[u8]), String> {
+    let length = data
+        .get(header..header + 4)
+        .map(|length| u32::from_le_bytes(length.try_into().unwrap()) as usize)
+        .ok_or("truncated length")?;
+    if length < header + 4 || length > data.len() {
+        return Err(format!("length {length} out of bounds"));
+    }
+
+    Ok((&data[header + 4..length], &data[length..]))
+}
+
+fn read_uleb128(data: &mut &[u8]) -> Result<u64, String> {
+    let mut value = 0u64;
+    for (index, &byte) in data.iter().enumerate() {
+        let shift = 7 * index as u32;
+        if shift >= u64::BITS {
+            break;
+        }
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            *data = &data[index + 1..];
+            return Ok(value);
+        }
+    }
+
+    Err("malformed ULEB128 number".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `.ARM.attributes` of a `thumbv8m.main-none-eabi` program
+    fn attributes(cpu_arch: u8) -> Vec<u8> {
+        let mut attributes = vec![];
+        attributes.extend(b"\x05cortex-m33\0"); // Tag_CPU_name
+        attributes.extend([TAG_CPU_ARCH as u8, cpu_arch]);
+        attributes.extend([0x07, b'M']); // Tag_CPU_arch_profile
+        attributes.extend([0x22, 0x01]); // Tag_CPU_unaligned_access
+
+        let mut file = vec![1];
+        file.extend((attributes.len() as u32 + 5).to_le_bytes());
+        file.extend(attributes);
+
+        let mut subsection = b"aeabi\0".to_vec();
+        subsection.extend(file);
+
+        let mut section = vec![b'A'];
+        section.extend((subsection.len() as u32 + 4).to_le_bytes());
+        section.extend(subsection);
+        section
+    }
+
+    #[test]
+    fn armv8m_attributes() {
+        assert_eq!(cpu_arch(&attributes(17)), Ok(Some(17)));
+        assert_eq!(cpu_arch(&attributes(13)), Ok(Some(13))); // ARMv7E-M
+        assert_eq!(cpu_arch(b"A"), Ok(None));
+        assert!(cpu_arch(b"B").is_err());
+        assert!(cpu_arch(&attributes(17)[..12]).is_err());
+    }
+
+    /// A relocatable ARM ELF file whose only section is `.ARM.attributes` with `attributes`
+    fn arm_elf(attributes: &[u8]) -> Vec<u8> {
+        const SHSTRTAB: &[u8] = b"\0.ARM.attributes\0.shstrtab\0";
+        let u16 = |value: u16| value.to_le_bytes();
+        let u32 = |value: u32| value.to_le_bytes();
+
+        let attributes_offset = 52;
+        let shstrtab_offset = attributes_offset + attributes.len();
+        let section_headers_offset = (shstrtab_offset + SHSTRTAB.len()).next_multiple_of(4);
+
+        let mut elf = b"\x7fELF\x01\x01\x01".to_vec();
+        elf.resize(16, 0);
+        elf.extend(u16(1)); // ET_REL
+        elf.extend(u16(40)); // EM_ARM
+        elf.extend(u32(1));
+        elf.extend(u32(0)); // e_entry
+        elf.extend(u32(0)); // e_phoff
+        elf.extend(u32(section_headers_offset as u32));
+        elf.extend(u32(0x0500_0000)); // EABI version 5
+        elf.extend(u16(52));
+        elf.extend(u16(32));
+        elf.extend(u16(0));
+        elf.extend(u16(40));
+        elf.extend(u16(3));
+        elf.extend(u16(2)); // e_shstrndx
+        elf.extend(attributes);
+        elf.extend(SHSTRTAB);
+        elf.resize(section_headers_offset, 0);
+
+        let mut section_header = |name: u32, kind: u32, offset: usize, size: usize| {
+            for field in [name, kind, 0, 0, offset as u32, size as u32, 0, 0, 1, 0] {
+                elf.extend(u32(field));
+            }
+        };
+        section_header(0, 0, 0, 0);
+        // SHT_ARM_ATTRIBUTES
+        section_header(1, 0x7000_0003, attributes_offset, attributes.len());
+        // SHT_STRTAB
+        section_header(17, 3, shstrtab_offset, SHSTRTAB.len());
+        elf
+    }
+
+    #[test]
+    fn from_object() {
+        let arch = |attributes: &[u8]| {
+            let elf = arm_elf(attributes);
+            Arch::from_object(&object::File::parse(elf.as_slice()).unwrap())
+        };
+
+        assert!(arch(&attributes(17)).has_stack_limit());
+        assert!(!arch(&attributes(13)).has_stack_limit());
+        // malformed or of an unknown format: no stack limit, but no error either
+        assert!(!arch(&attributes(17)[..12]).has_stack_limit());
+        assert!(!arch(b"B").has_stack_limit());
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Why a layout computation failed
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+    /// The result does not fit in 64 bits
+    Overflow,
+    /// The result is negative
+    Negative(i64),
+    /// The expression could not be evaluated at all
+    Invalid(String),
+}
+
+/// A linker-script expression or address computation that has no valid 64-bit result
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayoutError {
+    /// Linker script (or ELF file) the expression comes from, if known
+    pub script: Option<PathBuf>,
+    pub expression: String,
+    pub kind: ErrorKind,
+}
+
+impl LayoutError {
+    pub fn new(expression: impl Into<String>, kind: ErrorKind) -> Self {
+        Self {
+            script: None,
+            expression: expression.into(),
+            kind,
+        }
+    }
+
+    /// Attributes the error to `script`, unless it is already attributed to another file
+    pub fn in_script(mut self, script: &Path) -> Self {
+        self.script.get_or_insert_with(|| script.to_owned());
+        self
+    }
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expression = &self.expression;
+        match &self.kind {
+            ErrorKind::Overflow => write!(f, "`{expression}` overflows 64-bit arithmetic")?,
+            ErrorKind::Negative(value) => {
+                write!(f, "`{expression}` evaluates to a negative value ({value})")?
+            }
+            ErrorKind::Invalid(reason) => write!(f, "could not evaluate `{expression}`: {reason}")?,
+        }
+
+        if let Some(script) = &self.script {
+            write!(f, " (in {})", script.display())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+/// `a + b`, or an [`ErrorKind::Overflow`] naming `expression`
+pub fn add(a: u64, b: u64, expression: &str) -> Result<u64, LayoutError> {
+    a.checked_add(b).ok_or_else(|| {
+        LayoutError::new(
+            format!("{expression} = {a:#x} + {b:#x}"),
+            ErrorKind::Overflow,
+        )
+    })
+}
+
+/// `a - b`, or an [`ErrorKind::Negative`] naming `expression`
+pub fn sub(a: u64, b: u64, expression: &str) -> Result<u64, LayoutError> {
+    a.checked_sub(b).ok_or_else(|| {
+        let difference = i128::from(a) - i128::from(b);
+        LayoutError::new(
+            format!("{expression} = {a:#x} - {b:#x}"),
+            ErrorKind::Negative(i64::try_from(difference).unwrap_or(i64::MIN)),
+        )
+    })
+}
+
+/// `x` rounded up to the next multiple of `multiple`, or an [`ErrorKind::Overflow`] naming
+/// `expression`
+pub fn align_up(x: u64, multiple: u64, expression: &str) -> Result<u64, LayoutError> {
+    x.checked_next_multiple_of(multiple).ok_or_else(|| {
+        LayoutError::new(
+            format!("{expression} = ALIGN({x:#x}, {multiple})"),
+            ErrorKind::Overflow,
+        )
+    })
+}
@@ -0,0 +1,678 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    ops::Range,
+};
+
+use object::{
+    elf, Architecture, FileFlags, Object as _, ObjectSection as _, ObjectSymbol as _, SectionIndex,
+    SymbolKind,
+};
+
+use crate::{config::Config, stack_sizes::Frame};
+
+/// Registers the Cortex-M hardware pushes onto the stack when it takes an exception: R0-R3, R12,
+/// LR, PC and xPSR
+const ARM_EXCEPTION_FRAME: u64 = 32;
+/// Exception frame of a Cortex-M with an active FPU context: also S0-S15, FPSCR and padding
+const ARM_EXCEPTION_FRAME_FPU: u64 = 104;
+
+/// A function in the call graph
+#[derive(Debug, Default)]
+struct Function {
+    name: String,
+    /// Size of its stack frame; `None` if it is not in `.stack_sizes`, e.g. because it is written
+    /// in assembly
+    frame: Option<u64>,
+    /// Addresses of the functions it calls
+    calls: BTreeSet<u64>,
+    /// Does it call functions through pointers?
+    indirect: bool,
+}
+
+/// Functions by address, with the Thumb bit cleared
+#[derive(Debug, Default)]
+struct CallGraph {
+    functions: BTreeMap<u64, Function>,
+}
+
+/// Root of the call graph: the reset handler or an exception / interrupt handler
+#[derive(Debug, PartialEq)]
+pub struct Root {
+    pub name: String,
+    /// `None` for the reset handler, or a handler without a configured priority
+    pub priority: Option<u64>,
+    pub depth: u64,
+}
+
+/// Result of the worst-case stack depth analysis
+#[derive(Debug, Default, PartialEq)]
+pub struct Analysis {
+    /// Everything that runs on reset, starting with the reset handler
+    pub main: Option<Root>,
+    pub handlers: Vec<Root>,
+    /// Bytes the hardware pushes onto the stack per exception
+    pub exception_frame: u64,
+    /// Worst-case stack depth: `main` plus one handler per priority level, nested
+    pub depth: u64,
+    /// Functions that call other functions through pointers
+    pub indirect: BTreeSet<String>,
+    /// Functions that (mutually) recurse
+    pub recursive: BTreeSet<String>,
+    /// Functions without a known stack frame size
+    pub unknown: BTreeSet<String>,
+}
+
+impl Analysis {
+    /// Is `depth` an upper bound, or could it be more?
+    pub fn is_bounded(&self) -> bool {
+        self.indirect.is_empty() && self.recursive.is_empty() && self.unknown.is_empty()
+    }
+}
+
+/// Report of the analysis, one root per line
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(main) = &self.main {
+            writeln!(f, "  {:>8} bytes  {} (reset)", main.depth, main.name)?;
+        }
+        for handler in &self.handlers {
+            let priority = match handler.priority {
+                Some(priority) => format!("priority {priority}"),
+                None => "no priority configured".to_string(),
+            };
+            writeln!(
+                f,
+                "  {:>8} bytes  {} ({priority})",
+                handler.depth, handler.name
+            )?;
+        }
+        write!(
+            f,
+            "  {:>8} bytes  worst case, with {} bytes per nested exception",
+            self.depth, self.exception_frame
+        )
+    }
+}
+
+/// Can the call graph be read from the machine code of `architecture`?
+pub fn supports(architecture: Architecture) -> bool {
+    matches!(
+        architecture,
+        Architecture::Arm | Architecture::Riscv32 | Architecture::Riscv64
+    )
+}
+
+/// Computes the worst-case stack depth of the program in `object`
+///
+/// Handlers with the same priority in `config` cannot preempt each other; handlers without a
+/// configured priority are assumed to be able to preempt all others.
+pub fn analyze(
+    object: &object::File<'_>,
+    frames: &[Frame],
+    config: &Config,
+) -> Result<Analysis, String> {
+    let thumb = object.architecture() == Architecture::Arm;
+    let graph = CallGraph::new(object, frames)?;
+
+    let exception_frame = match (object.architecture(), object.flags()) {
+        (Architecture::Arm, FileFlags::Elf { e_flags, .. })
+            if e_flags & elf::EF_ARM_ABI_FLOAT_HARD != 0 =>
+        {
+            ARM_EXCEPTION_FRAME_FPU
+        }
+        (Architecture::Arm, _) => ARM_EXCEPTION_FRAME,
+        // the trap handler saves the registers itself, on its own stack frame
+        _ => 0,
+    };
+
+    let mut analysis = Analysis {
+        exception_frame,
+        ..Analysis::default()
+    };
+    let mut memo = HashMap::new();
+
+    let entry = normalize(object.entry(), thumb);
+    analysis.main = Some(Root {
+        name: graph.name(entry),
+        priority: None,
+        depth: graph.depth(entry, &mut memo, &mut vec![], &mut analysis),
+    });
+
+    for handler in handlers(object)?
+        .into_iter()
+        .map(|address| normalize(address, thumb))
+        .filter(|&address| address != entry)
+        .collect::<BTreeSet<_>>()
+    {
+        let name = graph.name(handler);
+        let priority = config
+            .interrupt_priorities
+            .iter()
+            .find(|(handler, _)| *handler == name)
+            .map(|(_, priority)| *priority);
+        let depth = graph.depth(handler, &mut memo, &mut vec![], &mut analysis);
+        analysis.handlers.push(Root {
+            name,
+            priority,
+            depth,
+        });
+    }
+
+    // handlers of the same priority level don't nest, so only the deepest one of each level counts
+    let mut levels = BTreeMap::new();
+    let mut depth = analysis.main.as_ref().map_or(0, |main| main.depth);
+    for handler in &analysis.handlers {
+        let handler_depth = handler.depth + exception_frame;
+        match handler.priority {
+            Some(priority) => {
+                let level = levels.entry(priority).or_insert(0);
+                *level = handler_depth.max(*level);
+            }
+            None => depth += handler_depth,
+        }
+    }
+    analysis.depth = depth + levels.values().sum::<u64>();
+
+    Ok(analysis)
+}
+
+impl CallGraph {
+    fn new(object: &object::File<'_>, frames: &[Frame]) -> Result<Self, String> {
+        let architecture = object.architecture();
+        let thumb = architecture == Architecture::Arm;
+        let frames = frames
+            .iter()
+            .map(|frame| (normalize(frame.address, thumb), frame.size))
+            .collect::<HashMap<_, _>>();
+        let data = data_ranges(object);
+
+        let mut graph = CallGraph::default();
+        for symbol in object.symbols() {
+            if symbol.kind() != SymbolKind::Text || symbol.size() == 0 {
+                continue;
+            }
+            let Some(section) = symbol
+                .section_index()
+                .and_then(|index| object.section_by_index(index).ok())
+            else {
+                continue;
+            };
+
+            let address = normalize(symbol.address(), thumb);
+            let name = symbol
+                .name()
+                .map(|name| rustc_demangle::demangle(name).to_string())
+                .unwrap_or_else(|_| format!("{address:#x}"));
+            let code = section
+                .data()
+                .ok()
+                .and_then(|data| {
+                    let start = usize::try_from(address.checked_sub(section.address())?).ok()?;
+                    data.get(start..start.checked_add(usize::try_from(symbol.size()).ok()?)?)
+                })
+                .ok_or_else(|| format!("could not read the code of {name}"))?;
+
+            let (calls, indirect) = match architecture {
+                Architecture::Arm => thumb_calls(code, address, &data),
+                Architecture::Riscv32 => riscv_calls(code, address, false),
+                Architecture::Riscv64 => riscv_calls(code, address, true),
+                other => return Err(format!("stack analysis does not support {other:?}")),
+            };
+
+            graph.functions.insert(
+                address,
+                Function {
+                    name,
+                    frame: frames.get(&address).copied(),
+                    calls: calls
+                        .into_iter()
+                        .map(|call| normalize(call, thumb))
+                        .collect(),
+                    indirect,
+                },
+            );
+        }
+
+        Ok(graph)
+    }
+
+    fn name(&self, address: u64) -> String {
+        match self.functions.get(&address) {
+            Some(function) => function.name.clone(),
+            None => format!("{address:#x}"),
+        }
+    }
+
+    /// Worst-case stack depth of the function at `address`, including its callees
+    ///
+    /// Whatever makes the result a lower bound is recorded in `analysis`.
+    fn depth(
+        &self,
+        address: u64,
+        memo: &mut HashMap<u64, u64>,
+        path: &mut Vec<u64>,
+        analysis: &mut Analysis,
+    ) -> u64 {
+        if let Some(&depth) = memo.get(&address) {
+            return depth;
+        }
+        if let Some(position) = path.iter().position(|&caller| caller == address) {
+            analysis
+                .recursive
+                .extend(path[position..].iter().map(|&caller| self.name(caller)));
+            return 0;
+        }
+
+        let Some(function) = self.functions.get(&address) else {
+            analysis.unknown.insert(self.name(address));
+            return 0;
+        };
+        if function.indirect {
+            analysis.indirect.insert(function.name.clone());
+        }
+        let frame = function.frame.unwrap_or_else(|| {
+            analysis.unknown.insert(function.name.clone());
+            0
+        });
+
+        path.push(address);
+        let callees = function
+            .calls
+            .iter()
+            .map(|&callee| self.depth(callee, memo, path, analysis))
+            .max()
+            .unwrap_or(0);
+        path.pop();
+
+        let depth = frame + callees;
+        memo.insert(address, depth);
+        depth
+    }
+}
+
+/// Clears the Thumb bit of ARM function addresses
+fn normalize(address: u64, thumb: bool) -> u64 {
+    if thumb {
+        address & !1
+    } else {
+        address
+    }
+}
+
+/// Addresses of the exception and interrupt handlers in the vector table
+fn handlers(object: &object::File<'_>) -> Result<Vec<u64>, String> {
+    match object.architecture() {
+        Architecture::Arm => {
+            let Some(section) = object.section_by_name(".vector_table") else {
+                return Ok(vec![]);
+            };
+            let table = section
+                .data()
+                .map_err(|e| format!("could not read .vector_table: {e}"))?;
+
+            // the first entry is the initial stack pointer, the second one the reset handler
+            Ok(table
+                .chunks_exact(4)
+                .skip(2)
+                .map(|entry| u64::from(u32::from_le_bytes(entry.try_into().unwrap())))
+                .filter(|&handler| handler != 0)
+                .collect())
+        }
+        // `riscv-rt` funnels all traps through `_start_trap`
+        _ => Ok(object
+            .symbols()
+            .find(|symbol| symbol.name() == Ok("_start_trap"))
+            .map(|symbol| symbol.address())
+            .into_iter()
+            .collect()),
+    }
+}
+
+/// Data (like literal pools) in between the code, according to the ARM `$d` / `$t` / `$a` mapping
+/// symbols
+fn data_ranges(object: &object::File<'_>) -> Vec<Range<u64>> {
+    let mut mapping_symbols = object
+        .symbols()
+        .filter_map(|symbol| {
+            let name = symbol.name().ok()?;
+            let kind = name.split('.').next()?;
+            let is_data = match kind {
+                "$d" => true,
+                "$t" | "$a" => false,
+                _ => return None,
+            };
+            Some((symbol.section_index()?.0, symbol.address(), is_data))
+        })
+        .collect::<Vec<_>>();
+    mapping_symbols.sort();
+
+    // data lasts until the next code mapping symbol in the same section, or the end of the section
+    mapping_symbols
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, _, is_data))| *is_data)
+        .filter_map(|(index, &(section, start, _))| {
+            let end = match mapping_symbols[index + 1..]
+                .iter()
+                .find(|(next_section, _, _)| *next_section == section)
+            {
+                Some(&(_, end, _)) => end,
+                None => {
+                    let section = object.section_by_index(SectionIndex(section)).ok()?;
+                    section.address() + section.size()
+                }
+            };
+            Some(start..end)
+        })
+        .collect()
+}
+
+/// Finds the `BL` and `BLX` calls in Thumb `code` that starts at `address`, and the tail calls:
+/// `B` and `B.W` to outside of the function
+///
+/// Returns the call targets, and whether there are indirect calls or jumps (`BLX <register>`,
+/// `BX <register>` other than `BX LR`).
+fn thumb_calls(code: &[u8], address: u64, data: &[Range<u64>]) -> (Vec<u64>, bool) {
+    let halfword = |offset: usize| {
+        code.get(offset..offset + 2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    };
+    let function = address..address + code.len() as u64;
+
+    let mut calls = vec![];
+    let mut indirect = false;
+    let mut offset = 0;
+    while let Some(first) = halfword(offset) {
+        let pc = address + offset as u64;
+        if let Some(data) = data.iter().find(|data| data.contains(&pc)) {
+            offset = usize::try_from(data.end - address).unwrap_or(code.len());
+            continue;
+        }
+
+        // 32-bit instructions start with 0b11101, 0b11110 or 0b11111
+        if first >> 11 < 0b11101 {
+            // BLX <Rm>, and BX <Rm> unless it returns
+            if first & 0xff87 == 0x4780 || (first & 0xff87 == 0x4700 && first != 0x4770) {
+                indirect = true;
+            }
+            // B (T2): 11100 imm11
+            if first >> 11 == 0b11100 {
+                let imm = i64::from((((u32::from(first) & 0x7ff) << 21) as i32) >> 20);
+                let target = (pc + 4).wrapping_add_signed(imm);
+                if !function.contains(&target) {
+                    calls.push(target);
+                }
+            }
+            offset += 2;
+            continue;
+        }
+
+        let Some(second) = halfword(offset + 2) else {
+            break;
+        };
+        // BL: 11110 S imm10, 11 J1 1 J2 imm11; BLX: same, but with bit 12 of the second halfword
+        // clear; B.W: same as BL, but with bit 14 of the second halfword clear
+        let call = second & 0xc000 == 0xc000;
+        let jump = second & 0xd000 == 0x9000;
+        if first >> 11 == 0b11110 && (call || jump) {
+            let s = u32::from(first >> 10) & 1;
+            let i1 = !(u32::from(second >> 13) ^ s) & 1;
+            let i2 = !(u32::from(second >> 11) ^ s) & 1;
+            let imm10 = u32::from(first) & 0x3ff;
+            let imm11 = u32::from(second) & 0x7ff;
+            let imm = (s << 24) | (i1 << 23) | (i2 << 22) | (imm10 << 12) | (imm11 << 1);
+            // sign-extend the 25-bit offset
+            let imm = i64::from(((imm << 7) as i32) >> 7);
+
+            let pc = pc + 4;
+            let pc = if second & 0x1000 == 0 { pc & !3 } else { pc };
+            let target = pc.wrapping_add_signed(imm);
+            // a jump within the function is just control flow
+            if call || !function.contains(&target) {
+                calls.push(target);
+            }
+        }
+        offset += 4;
+    }
+
+    (calls, indirect)
+}
+
+/// Finds the calls in RISC-V `code` that starts at `address`: `JAL`, `C.JAL`, `JALR` and
+/// `C.JALR`, where `JALR` is resolved if it follows an `AUIPC`; and the tail calls: the same jumps
+/// with `rd` = `zero` (`J`, `C.J`, `JR`, `C.JR`) to outside of the function
+///
+/// Returns the call targets, and whether there are indirect calls or jumps, other than `ret`.
+fn riscv_calls(code: &[u8], address: u64, rv64: bool) -> (Vec<u64>, bool) {
+    let function = address..address + code.len() as u64;
+    let mut calls = vec![];
+    let mut indirect = false;
+    // destination register and value of the previous instruction, if it was an `AUIPC`
+    let mut auipc = None;
+    let mut offset = 0;
+    while let Some(bytes) = code.get(offset..offset + 2) {
+        let pc = address + offset as u64;
+        let low = u16::from_le_bytes([bytes[0], bytes[1]]);
+
+        if low & 0b11 != 0b11 {
+            let op = low & 0b11;
+            let funct3 = low >> 13;
+            let jump = op == 0b01 && funct3 == 0b101;
+            if op == 0b01 && ((funct3 == 0b001 && !rv64) || jump) {
+                // C.JAL and C.J: imm[11|4|9:8|10|6|7|3:1|5]
+                let bit = |from: u16, to: u32| u32::from((low >> from) & 1) << to;
+                let imm = bit(12, 11)
+                    | bit(11, 4)
+                    | (u32::from((low >> 9) & 0b11) << 8)
+                    | bit(8, 10)
+                    | bit(7, 6)
+                    | bit(6, 7)
+                    | (u32::from((low >> 3) & 0b111) << 1)
+                    | bit(2, 5);
+                let imm = i64::from(((imm << 20) as i32) >> 20);
+                let target = pc.wrapping_add_signed(imm);
+                if !jump || !function.contains(&target) {
+                    calls.push(target);
+                }
+            } else if op == 0b10
+                && matches!(low >> 12, 0b1000 | 0b1001)
+                && (low >> 7) & 0x1f != 0
+                && (low >> 2) & 0x1f == 0
+                // `c.jr ra` is `ret`
+                && low != 0x8082
+            {
+                // C.JALR and C.JR
+                indirect = true;
+            }
+            auipc = None;
+            offset += 2;
+            continue;
+        }
+
+        let Some(bytes) = code.get(offset..offset + 4) else {
+            break;
+        };
+        let instruction = u32::from_le_bytes(bytes.try_into().unwrap());
+        let opcode = instruction & 0x7f;
+        let rd = (instruction >> 7) & 0x1f;
+        let rs1 = (instruction >> 15) & 0x1f;
+        let previous = auipc.take();
+        match opcode {
+            // AUIPC
+            0x17 => {
+                let upper = i64::from((instruction & 0xffff_f000) as i32);
+                auipc = Some((rd, pc.wrapping_add_signed(upper)));
+            }
+            // JAL; with `rd` = `zero` a plain jump, which is a tail call if it leaves the function
+            0x6f => {
+                let imm = ((instruction >> 31) << 20)
+                    | (((instruction >> 12) & 0xff) << 12)
+                    | (((instruction >> 20) & 1) << 11)
+                    | (((instruction >> 21) & 0x3ff) << 1);
+                let imm = i64::from(((imm << 11) as i32) >> 11);
+                let target = pc.wrapping_add_signed(imm);
+                if rd != 0 || !function.contains(&target) {
+                    calls.push(target);
+                }
+            }
+            // JALR, except for `ret` (`jalr zero, 0(ra)`)
+            0x67 if instruction != 0x0000_8067 => match previous {
+                Some((register, upper)) if register == rs1 => {
+                    let imm = i64::from((instruction as i32) >> 20);
+                    calls.push(upper.wrapping_add_signed(imm));
+                }
+                _ => indirect = true,
+            },
+            _ => {}
+        }
+        offset += 4;
+    }
+
+    (calls, indirect)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes `BL <to>` at `from`
+    fn bl(from: u64, to: u64) -> [u8; 4] {
+        let imm = (to as i64 - (from as i64 + 4)) as u32;
+        let s = (imm >> 24) & 1;
+        let j1 = (!(imm >> 23) ^ s) & 1;
+        let j2 = (!(imm >> 22) ^ s) & 1;
+        let first = 0xf000 | (s << 10) | ((imm >> 12) & 0x3ff);
+        let second = 0xd000 | (j1 << 13) | (j2 << 11) | ((imm >> 1) & 0x7ff);
+        let [a, b] = (first as u16).to_le_bytes();
+        let [c, d] = (second as u16).to_le_bytes();
+        [a, b, c, d]
+    }
+
+    #[test]
+    fn supported_architectures() {
+        assert!(supports(Architecture::Arm));
+        assert!(supports(Architecture::Riscv32));
+        assert!(!supports(Architecture::Msp430));
+    }
+
+    #[test]
+    fn thumb() {
+        // bl 0x200 (as assembled by LLVM)
+        assert_eq!(bl(0x100, 0x200), [0x00, 0xf0, 0x7e, 0xf8]);
+
+        let mut code = vec![];
+        code.extend(bl(0x100, 0x200));
+        code.extend([0x00, 0xbf]); // nop
+        code.extend(bl(0x106, 0x40)); // backwards
+        code.extend([0x98, 0x47]); // blx r3
+        code.extend(bl(0x10c, 0x300)); // in a literal pool: not code
+        let literal_pool = 0x10c..0x110;
+        let (calls, indirect) = thumb_calls(&code, 0x100, &[literal_pool]);
+        assert_eq!(calls, [0x200, 0x40]);
+        assert!(indirect);
+    }
+
+    #[test]
+    fn thumb_tail_calls() {
+        // B.W is BL with bit 14 of the second halfword clear
+        let b_w = |from, to| {
+            let [a, b, c, d] = bl(from, to);
+            [a, b, c, d & !0x40]
+        };
+
+        let mut code = vec![];
+        code.extend(b_w(0x100, 0x108)); // b.w within the function: not a call
+        code.extend([0x01, 0xe0]); // b 0x10a, within the function
+        code.extend([0x70, 0x47]); // bx lr
+        code.extend(b_w(0x108, 0x400)); // b.w 0x400: a tail call
+        code.extend([0x7f, 0xe0]); // b 0x20e: a tail call
+        let (calls, indirect) = thumb_calls(&code, 0x100, &[]);
+        assert_eq!(calls, [0x400, 0x20e]);
+        assert!(!indirect);
+
+        let (_, indirect) = thumb_calls(&[0x18, 0x47], 0x100, &[]); // bx r3
+        assert!(indirect);
+    }
+
+    #[test]
+    fn riscv() {
+        let code = [
+            0x100000efu32.to_le_bytes().to_vec(), // jal ra, +0x100
+            0x00001097u32.to_le_bytes().to_vec(), // auipc ra, 0x1
+            0x010080e7u32.to_le_bytes().to_vec(), // jalr ra, 16(ra)
+            0x2021u16.to_le_bytes().to_vec(),     // c.jal +8
+            0x0000006fu32.to_le_bytes().to_vec(), // j . (not a call)
+        ]
+        .concat();
+        let (calls, indirect) = riscv_calls(&code, 0x1000, false);
+        assert_eq!(calls, [0x1100, 0x2014, 0x1014]);
+        assert!(!indirect);
+
+        let (_, indirect) = riscv_calls(&0x9502u16.to_le_bytes(), 0, false); // c.jalr a0
+        assert!(indirect);
+    }
+
+    #[test]
+    fn riscv_tail_calls() {
+        let code = [
+            0x00c0006fu32.to_le_bytes().to_vec(), // j +12, within the function: not a call
+            0x00000317u32.to_le_bytes().to_vec(), // auipc t1, 0
+            0x10030067u32.to_le_bytes().to_vec(), // jr 256(t1): a tail call
+            0x1000006fu32.to_le_bytes().to_vec(), // j +0x100: a tail call
+            0xa025u16.to_le_bytes().to_vec(),     // c.j +40: a tail call
+            0x8082u16.to_le_bytes().to_vec(),     // ret
+            0x00008067u32.to_le_bytes().to_vec(), // ret, uncompressed
+        ]
+        .concat();
+        let (calls, indirect) = riscv_calls(&code, 0x1000, false);
+        assert_eq!(calls, [0x1104, 0x110c, 0x1038]);
+        assert!(!indirect);
+
+        let (_, indirect) = riscv_calls(&0x8502u16.to_le_bytes(), 0, false); // c.jr a0
+        assert!(indirect);
+        let (_, indirect) = riscv_calls(&0x00050067u32.to_le_bytes(), 0, false); // jr a0
+        assert!(indirect);
+    }
+
+    #[test]
+    fn depth() {
+        let mut graph = CallGraph::default();
+        let mut function = |address, name: &str, frame, calls: &[u64]| {
+            graph.functions.insert(
+                address,
+                Function {
+                    name: name.to_string(),
+                    frame,
+                    calls: calls.iter().copied().collect(),
+                    indirect: false,
+                },
+            );
+        };
+        function(0x100, "main", Some(16), &[0x200, 0x300]);
+        function(0x200, "small", Some(8), &[]);
+        function(0x300, "large", Some(64), &[0x200]);
+        function(0x400, "even", Some(8), &[0x500]);
+        function(0x500, "odd", Some(8), &[0x400]);
+        function(0x600, "asm", None, &[]);
+
+        let mut analysis = Analysis::default();
+        let mut memo = HashMap::new();
+        assert_eq!(
+            graph.depth(0x100, &mut memo, &mut vec![], &mut analysis),
+            16 + 64 + 8
+        );
+        assert!(analysis.is_bounded());
+
+        assert_eq!(
+            graph.depth(0x400, &mut memo, &mut vec![], &mut analysis),
+            16
+        );
+        assert_eq!(graph.depth(0x600, &mut memo, &mut vec![], &mut analysis), 0);
+        assert_eq!(
+            analysis.recursive,
+            ["even".to_string(), "odd".to_string()].into()
+        );
+        assert_eq!(analysis.unknown, ["asm".to_string()].into());
+    }
+}
@@ -0,0 +1,761 @@
+use std::{
+    env, fmt, fs, mem,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// Name of the configuration file, looked up in the directory the linker is invoked from
+pub const FILE_NAME: &str = "flip-link.toml";
+/// Environment variable that points to a configuration file somewhere else
+pub const ENV_VAR: &str = "FLIP_LINK_CONFIG";
+/// Prefix of linker arguments that are meant for `flip-link` and not for the linker
+pub const ARG_PREFIX: &str = "--flip-link-";
+
+/// Options that change the layout `flip-link` produces
+///
+/// Every option can be set in the configuration file (`key = value`) and as a linker argument
+/// (`--flip-link-key=value`); linker arguments take precedence.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    /// Size of the MPU guard band at the bottom of the stack
+    pub mpu_guard: Option<u64>,
+    /// File to write the MPU region settings for the guard band to
+    pub mpu_guard_file: Option<PathBuf>,
+    /// Turn warnings into errors
+    pub strict: bool,
+    /// `MEMORY` region to put the stack into, instead of below the statics in RAM
+    pub stack_region: Option<String>,
+    /// Size of the heap; no heap is placed if this is not set
+    pub heap_size: Option<u64>,
+    /// Where the heap goes relative to the stack
+    pub heap_placement: HeapPlacement,
+    /// Stacks for other cores, or other execution contexts, to place below the statics
+    pub extra_stacks: Vec<ExtraStack>,
+    /// Smallest main stack the program may get; linking fails if less RAM is left for it
+    pub min_stack_size: Option<u64>,
+    /// Compute the worst-case stack depth from the call graph
+    pub stack_analysis: bool,
+    /// Priorities of the exception and interrupt handlers, by name, for the stack analysis
+    pub interrupt_priorities: Vec<(String, u64)>,
+    /// Size of the stack reserved for the fault handler
+    pub fault_stack: Option<u64>,
+    /// `MEMORY` region to put the fault stack into, instead of RAM
+    pub fault_stack_region: Option<String>,
+    /// Size of the canary block at the bottom of the stack
+    pub canary: Option<u64>,
+    /// Sections to keep at a fixed offset below the end of RAM, by name
+    pub pinned_sections: Vec<(String, u64)>,
+    /// Bytes at the end of RAM that flip-link leaves alone
+    pub reserved_top: Option<u64>,
+    /// Bytes at the start of RAM that flip-link leaves alone
+    pub reserved_bottom: Option<u64>,
+    /// Output sections of task stacks to move to the bottom of RAM, besides `.task_stacks`
+    pub task_stack_sections: Vec<String>,
+    /// Gap above each task stack section
+    pub task_stack_gap: Option<u64>,
+    /// Output sections that reserve the main stack, besides `.stack`
+    pub stack_sections: Vec<String>,
+    /// Custom order of the areas of RAM, from the bottom up; empty for the default flipped layout
+    pub layout: Vec<LayoutItem>,
+    /// Memory-mapped peripheral space, instead of the one the architecture has
+    pub peripheral_space: Option<Range<u64>>,
+    /// Try to link only once, with a linker script that puts the statics at the end of RAM itself
+    pub single_pass: bool,
+}
+
+/// A stack besides the main one, like the one for the second core of the RP2040
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtraStack {
+    /// Used in the names of the exported symbols, e.g. `_core1_stack_start`
+    pub name: String,
+    pub size: u64,
+}
+
+impl ExtraStack {
+    /// Parses `name: size`
+    fn parse(stack: &str) -> Result<Self, String> {
+        let (name, size) = parse_named(stack, "size", is_identifier)?;
+        Ok(Self { name, size })
+    }
+}
+
+/// An area of RAM in the `layout` option
+#[derive(Clone, Debug, PartialEq)]
+pub enum LayoutItem {
+    /// `stack <size>`, or `stack *` for all of the RAM the other areas leave
+    Stack(Option<u64>),
+    /// `statics`: the sections the linker script puts into RAM
+    Statics,
+    /// `heap <size>`
+    Heap(u64),
+    /// `stack:<name> <size>`
+    ExtraStack(ExtraStack),
+    /// `fault-stack <size>`
+    FaultStack(u64),
+    /// `guard <size>`: an MPU guard band
+    Guard(u64),
+    /// `canary <size>`, right below the stack
+    Canary(u64),
+    /// `reserved <size>`, at the start or the end of RAM
+    Reserved(u64),
+    /// `.<section>`: an output section of its own, moved with `--section-start`
+    Section(String),
+}
+
+impl LayoutItem {
+    /// Parses `kind [size]`
+    fn parse(item: &str) -> Result<Self, String> {
+        let item = item.trim();
+        let (kind, raw_size) = item
+            .split_once(char::is_whitespace)
+            .map_or((item, None), |(kind, size)| (kind, Some(size.trim())));
+        let size = || -> Result<u64, String> {
+            let size = raw_size.ok_or_else(|| format!("`{kind}` needs a size"))?;
+            crate::evaluate_expression(size).map_err(|e| e.to_string())
+        };
+        let no_size = |item: Self| match raw_size {
+            None => Ok(item),
+            Some(_) => Err(format!("`{kind}` does not take a size")),
+        };
+
+        match kind {
+            "stack" if raw_size == Some("*") => Ok(Self::Stack(None)),
+            "stack" => Ok(Self::Stack(Some(size()?))),
+            "statics" => no_size(Self::Statics),
+            "heap" => Ok(Self::Heap(size()?)),
+            "fault-stack" => Ok(Self::FaultStack(size()?)),
+            "guard" => Ok(Self::Guard(size()?)),
+            "canary" => Ok(Self::Canary(size()?)),
+            "reserved" => Ok(Self::Reserved(size()?)),
+            _ if kind.starts_with('.') && kind.chars().all(is_section_name) => {
+                no_size(Self::Section(kind.to_owned()))
+            }
+            _ => match kind.strip_prefix("stack:") {
+                Some(name) if !name.is_empty() && name.chars().all(is_identifier) => {
+                    Ok(Self::ExtraStack(ExtraStack {
+                        name: name.to_owned(),
+                        size: size()?,
+                    }))
+                }
+                _ => Err(format!("unknown layout item `{item}`")),
+            },
+        }
+    }
+
+    fn is_same_area(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::ExtraStack(a), Self::ExtraStack(b)) => a.name == b.name,
+            (Self::Section(a), Self::Section(b)) => a == b,
+            (Self::Reserved(_), Self::Reserved(_)) => false,
+            _ => mem::discriminant(self) == mem::discriminant(other),
+        }
+    }
+}
+
+impl fmt::Display for LayoutItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stack(_) => f.write_str("stack"),
+            Self::Statics => f.write_str("statics"),
+            Self::Heap(_) => f.write_str("heap"),
+            Self::ExtraStack(stack) => write!(f, "{} stack", stack.name),
+            Self::FaultStack(_) => f.write_str("fault stack"),
+            Self::Guard(_) => f.write_str("MPU guard"),
+            Self::Canary(_) => f.write_str("canary"),
+            Self::Reserved(_) => f.write_str("reserved area"),
+            Self::Section(name) => write!(f, "{name} section"),
+        }
+    }
+}
+
+/// Where the heap goes relative to the stack
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum HeapPlacement {
+    /// stack -> heap -> statics, from the bottom of RAM upwards
+    #[default]
+    AboveStack,
+    /// heap -> stack -> statics, from the bottom of RAM upwards
+    BelowStack,
+}
+
+impl Config {
+    /// Loads the configuration file, if there is one, and applies the `--flip-link-*` arguments
+    ///
+    /// Returns the configuration and `args` without the `--flip-link-*` arguments, which the
+    /// linker would reject.
+    // NOTE `--flip-link-*` arguments inside of `@file`s are not supported; they'd reach the linker
+    pub fn load(args: &[String], current_dir: &Path) -> crate::Result<(Self, Vec<String>)> {
+        let mut config = Config::default();
+
+        let path = match env::var_os(ENV_VAR) {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Some(current_dir.join(FILE_NAME)).filter(|path| path.is_file()),
+        };
+        if let Some(path) = path {
+            log::info!("loading configuration from {}", path.display());
+            let contents = fs::read_to_string(&path)
+                .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+            config
+                .parse_file(&contents)
+                .map_err(|e| format!("{}:{e}", path.display()))?;
+        }
+
+        let linker_args = config.parse_args(args)?;
+
+        config.check_layout()?;
+        log::debug!("{config:?}");
+
+        Ok((config, linker_args))
+    }
+
+    /// Applies the `--flip-link-*` arguments in `args` on top of the configuration, and returns the
+    /// other arguments, which are meant for the linker
+    fn parse_args(&mut self, args: &[String]) -> Result<Vec<String>, String> {
+        let mut linker_args = Vec::with_capacity(args.len());
+        for arg in args {
+            let Some(option) = arg.strip_prefix(ARG_PREFIX) else {
+                linker_args.push(arg.clone());
+                continue;
+            };
+
+            let (key, value) = option.split_once('=').unwrap_or((option, "true"));
+            let value = Value::parse_arg(value).map_err(|e| format!("{arg}: {e}"))?;
+            self.set(key, value).map_err(|e| format!("{arg}: {e}"))?;
+        }
+        Ok(linker_args)
+    }
+
+    /// Checks that the `layout` describes each area at most once, and in a place that makes sense
+    fn check_layout(&self) -> Result<(), String> {
+        if self.layout.is_empty() {
+            return Ok(());
+        }
+
+        let replaced = [
+            ("mpu-guard", self.mpu_guard.is_some()),
+            ("stack-region", self.stack_region.is_some()),
+            ("heap-size", self.heap_size.is_some()),
+            (
+                "heap-placement",
+                self.heap_placement != HeapPlacement::default(),
+            ),
+            ("extra-stacks", !self.extra_stacks.is_empty()),
+            ("fault-stack", self.fault_stack.is_some()),
+            ("fault-stack-region", self.fault_stack_region.is_some()),
+            ("canary", self.canary.is_some()),
+            ("pinned-sections", !self.pinned_sections.is_empty()),
+            ("reserved-top", self.reserved_top.is_some()),
+            ("reserved-bottom", self.reserved_bottom.is_some()),
+            ("task-stack-sections", !self.task_stack_sections.is_empty()),
+            ("task-stack-gap", self.task_stack_gap.is_some()),
+        ];
+        if let Some((option, _)) = replaced.iter().find(|(_, is_set)| *is_set) {
+            return Err(format!(
+                "the `layout` option replaces the `{option}` option; describe the area in the \
+                layout instead"
+            ));
+        }
+
+        for (index, item) in self.layout.iter().enumerate() {
+            if self.layout[..index]
+                .iter()
+                .any(|other| item.is_same_area(other))
+            {
+                return Err(format!("the layout contains the {item} more than once"));
+            }
+
+            match item {
+                LayoutItem::Reserved(_) if index != 0 && index != self.layout.len() - 1 => {
+                    return Err(
+                        "a reserved area can only be the first or the last item of the layout"
+                            .to_string(),
+                    );
+                }
+                LayoutItem::Canary(_)
+                    if !matches!(self.layout.get(index + 1), Some(LayoutItem::Stack(_))) =>
+                {
+                    return Err(
+                        "the canary has to come right before the stack in the layout".to_string(),
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        for required in [LayoutItem::Stack(None), LayoutItem::Statics] {
+            if !self.layout.iter().any(|item| item.is_same_area(&required)) {
+                return Err(format!("the layout has no {required}"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The area right below the stack, if the stack is not at the bottom of RAM
+    ///
+    /// That is the area before the stack in the `layout`, or the heap with `heap-placement =
+    /// "below-stack"` when neither a guard band nor a canary separates the two.
+    pub fn below_stack(&self) -> Option<LayoutItem> {
+        if self.layout.is_empty() {
+            return match (self.heap_size, self.heap_placement) {
+                (Some(size), HeapPlacement::BelowStack)
+                    if self.stack_region.is_none()
+                        && self.mpu_guard.is_none()
+                        && self.canary.is_none() =>
+                {
+                    Some(LayoutItem::Heap(size))
+                }
+                _ => None,
+            };
+        }
+
+        let stack = self
+            .layout
+            .iter()
+            .position(|item| matches!(item, LayoutItem::Stack(_)))?;
+        self.layout[..stack].last().cloned()
+    }
+
+    /// Applies the `key = value` lines of a configuration file
+    ///
+    /// Errors are prefixed with the line number
+    // FIXME this is a dumb line-by-line parser for a small subset of TOML
+    fn parse_file(&mut self, contents: &str) -> Result<(), String> {
+        let mut lines = contents.lines().enumerate();
+        while let Some((index, line)) = lines.next() {
+            let line_number = index + 1;
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!(
+                    "{line_number}: expected `key = value`, found `{line}`"
+                ));
+            };
+            let key = key.trim();
+
+            // arrays may span several lines
+            let mut value = value.trim().to_owned();
+            if value.starts_with('[') {
+                while !value.ends_with(']') {
+                    let (_, line) = lines
+                        .next()
+                        .ok_or_else(|| format!("{line_number}: unterminated array"))?;
+                    value.push(' ');
+                    value.push_str(strip_comment(line).trim());
+                }
+            }
+
+            let value = Value::parse(&value).map_err(|e| format!("{line_number}: {e}"))?;
+            self.set(key, value)
+                .map_err(|e| format!("{line_number}: {key}: {e}"))?;
+        }
+
+        Ok(())
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match key {
+            "mpu-guard" => self.mpu_guard = Some(value.size()?),
+            "mpu-guard-file" => self.mpu_guard_file = Some(value.string()?.into()),
+            "strict" => self.strict = value.bool()?,
+            "stack-region" => self.stack_region = Some(value.string()?),
+            "heap-size" => self.heap_size = Some(value.size()?),
+            "heap-placement" => {
+                self.heap_placement = match value.string()?.as_str() {
+                    "above-stack" => HeapPlacement::AboveStack,
+                    "below-stack" => HeapPlacement::BelowStack,
+                    other => {
+                        return Err(format!(
+                            "expected `above-stack` or `below-stack`, found `{other}`"
+                        ))
+                    }
+                }
+            }
+            "extra-stacks" => {
+                for stack in value.list()? {
+                    self.extra_stacks.push(ExtraStack::parse(&stack)?);
+                }
+            }
+            "min-stack-size" => self.min_stack_size = Some(value.size()?),
+            "stack-analysis" => self.stack_analysis = value.bool()?,
+            "fault-stack" => self.fault_stack = Some(value.size()?),
+            "fault-stack-region" => self.fault_stack_region = Some(value.string()?),
+            "canary" => self.canary = Some(value.size()?),
+            "task-stack-sections" => {
+                for section in value.list()? {
+                    self.task_stack_sections.push(parse_section_name(&section)?);
+                }
+            }
+            "stack-sections" => {
+                for section in value.list()? {
+                    self.stack_sections.push(parse_section_name(&section)?);
+                }
+            }
+            "task-stack-gap" => self.task_stack_gap = Some(value.size()?),
+            "layout" => {
+                for item in value.list()? {
+                    self.layout.push(LayoutItem::parse(&item)?);
+                }
+            }
+            "single-pass" => self.single_pass = value.bool()?,
+            "peripheral-space" => self.peripheral_space = Some(parse_range(&value.string()?)?),
+            "reserved-top" => self.reserved_top = Some(value.size()?),
+            "reserved-bottom" => self.reserved_bottom = Some(value.size()?),
+            "pinned-sections" => {
+                for section in value.list()? {
+                    self.pinned_sections
+                        .push(parse_named(&section, "offset", is_section_name)?);
+                }
+            }
+            "interrupt-priorities" => {
+                for priority in value.list()? {
+                    self.interrupt_priorities.push(parse_named(
+                        &priority,
+                        "priority",
+                        is_identifier,
+                    )?);
+                }
+            }
+            _ => return Err(format!("unknown option `{key}`")),
+        }
+
+        Ok(())
+    }
+}
+
+/// Value of an option
+#[derive(Debug, PartialEq)]
+enum Value {
+    Bool(bool),
+    Size(u64),
+    String(String),
+    List(Vec<String>),
+}
+
+impl Value {
+    /// Parses a value in the configuration file
+    fn parse(value: &str) -> Result<Self, String> {
+        if let Some(list) = value.strip_prefix('[') {
+            let list = list
+                .strip_suffix(']')
+                .ok_or("expected `]` at the end of the array")?;
+            return list
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| unquote(item).ok_or(format!("expected a string, found `{item}`")))
+                .collect::<Result<_, _>>()
+                .map(Value::List);
+        }
+
+        if let Some(string) = unquote(value) {
+            return Ok(Value::String(string));
+        }
+
+        match value {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => crate::evaluate_expression(value)
+                .map(Value::Size)
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Parses the value of a `--flip-link-*` argument, where strings don't need quotes
+    fn parse_arg(value: &str) -> Result<Self, String> {
+        Value::parse(value).or_else(|_| Ok(Value::String(value.to_owned())))
+    }
+
+    fn bool(self) -> Result<bool, String> {
+        match self {
+            Value::Bool(bool) => Ok(bool),
+            value => Err(format!("expected `true` or `false`, found {value:?}")),
+        }
+    }
+
+    fn size(self) -> Result<u64, String> {
+        match self {
+            Value::Size(size) => Ok(size),
+            value => Err(format!("expected a size, found {value:?}")),
+        }
+    }
+
+    /// A list, or a single string as a list of one; the latter is how lists are passed as linker
+    /// arguments, one argument per item
+    fn list(self) -> Result<Vec<String>, String> {
+        match self {
+            Value::List(list) => Ok(list),
+            Value::String(string) => Ok(vec![string]),
+            value => Err(format!("expected an array of strings, found {value:?}")),
+        }
+    }
+
+    fn string(self) -> Result<String, String> {
+        match self {
+            Value::String(string) => Ok(string),
+            value => Err(format!("expected a string, found {value:?}")),
+        }
+    }
+}
+
+/// Parses a `name: number` list item, where `name` consists of `is_name` characters
+fn parse_named(item: &str, what: &str, is_name: fn(char) -> bool) -> Result<(String, u64), String> {
+    let (name, number) = item
+        .rsplit_once(':')
+        .ok_or_else(|| format!("expected `name: {what}`, found `{item}`"))?;
+    let name = name.trim();
+    if name.is_empty() || !name.chars().all(is_name) {
+        return Err(format!("`{name}` is not a valid name"));
+    }
+    let number = crate::evaluate_expression(number).map_err(|e| e.to_string())?;
+
+    Ok((name.to_owned(), number))
+}
+
+/// Parses a `start..end` address range
+fn parse_range(range: &str) -> Result<Range<u64>, String> {
+    let (start, end) = range
+        .split_once("..")
+        .ok_or_else(|| format!("expected `start..end`, found `{range}`"))?;
+    let start = crate::evaluate_expression(start).map_err(|e| e.to_string())?;
+    let end = crate::evaluate_expression(end).map_err(|e| e.to_string())?;
+    if start >= end {
+        return Err(format!("`{range}` is an empty range"));
+    }
+
+    Ok(start..end)
+}
+
+fn parse_section_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || !name.chars().all(is_section_name) {
+        return Err(format!("`{name}` is not a valid section name"));
+    }
+    Ok(name.to_owned())
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn is_section_name(c: char) -> bool {
+    is_identifier(c) || c == '.' || c == '$'
+}
+
+/// Removes a trailing `# comment`
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+/// Strips the quotes from a `"string"`
+fn unquote(value: &str) -> Option<String> {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .map(str::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_file() {
+        const CONFIG: &str = r#"
+        # MPU guard band below the stack
+        mpu-guard = 1K   # must be a power of two
+        mpu-guard-file = "target/mpu-guard.rs"
+        strict = true
+        stack-region = "CCMRAM"
+        heap-size = 8K
+        heap-placement = "below-stack"
+        extra-stacks = [
+            "core1: 4K",  # RP2040 core1
+            "irq: 1K",
+        ]
+        min-stack-size = 2K
+        stack-analysis = true
+        interrupt-priorities = ["SysTick: 1", "USART1: 2"]
+        fault-stack = 512
+        fault-stack-region = "CCMRAM"
+        canary = 32
+        pinned-sections = [".uninit: 1K", ".noinit.mailbox: 0x1100"]
+        reserved-top = 256
+        reserved-bottom = 4
+        task-stack-sections = [".embassy_arena"]
+        task-stack-gap = 64
+        stack-sections = "._user_stack"
+        single-pass = true
+        peripheral-space = "0x0000..0x1000"
+        "#;
+
+        let mut config = Config::default();
+        config.parse_file(CONFIG).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                mpu_guard: Some(1024),
+                mpu_guard_file: Some("target/mpu-guard.rs".into()),
+                strict: true,
+                stack_region: Some("CCMRAM".to_string()),
+                heap_size: Some(8 * 1024),
+                heap_placement: HeapPlacement::BelowStack,
+                extra_stacks: vec![
+                    ExtraStack {
+                        name: "core1".to_string(),
+                        size: 4096,
+                    },
+                    ExtraStack {
+                        name: "irq".to_string(),
+                        size: 1024,
+                    },
+                ],
+                min_stack_size: Some(2048),
+                stack_analysis: true,
+                interrupt_priorities: vec![("SysTick".to_string(), 1), ("USART1".to_string(), 2)],
+                fault_stack: Some(512),
+                fault_stack_region: Some("CCMRAM".to_string()),
+                canary: Some(32),
+                pinned_sections: vec![
+                    (".uninit".to_string(), 1024),
+                    (".noinit.mailbox".to_string(), 0x1100)
+                ],
+                reserved_top: Some(256),
+                reserved_bottom: Some(4),
+                task_stack_sections: vec![".embassy_arena".to_string()],
+                task_stack_gap: Some(64),
+                stack_sections: vec!["._user_stack".to_string()],
+                layout: vec![],
+                peripheral_space: Some(0x0000..0x1000),
+                single_pass: true,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_file_errors() {
+        let mut config = Config::default();
+        assert_eq!(
+            config.parse_file("\nstack-size = 1K"),
+            Err("2: stack-size: unknown option `stack-size`".to_string())
+        );
+        assert_eq!(
+            config.parse_file("mpu-guard = \"1K\""),
+            Err("1: mpu-guard: expected a size, found String(\"1K\")".to_string())
+        );
+        assert!(config.parse_file("mpu-guard").is_err());
+        assert!(config.parse_file("peripheral-space = \"0x1000\"").is_err());
+        assert!(config
+            .parse_file("peripheral-space = \"0x1000..0x1000\"")
+            .is_err());
+    }
+
+    #[test]
+    fn heap_below_stack() {
+        let mut config = Config {
+            heap_size: Some(1024),
+            heap_placement: HeapPlacement::BelowStack,
+            ..Config::default()
+        };
+        assert_eq!(config.below_stack(), Some(LayoutItem::Heap(1024)));
+
+        config.mpu_guard = Some(256);
+        assert_eq!(config.below_stack(), None);
+        config.mpu_guard = None;
+        config.heap_placement = HeapPlacement::AboveStack;
+        assert_eq!(config.below_stack(), None);
+    }
+
+    #[test]
+    fn layout() {
+        let mut config = Config::default();
+        config
+            .parse_file(
+                r#"layout = ["reserved 64", "fault-stack 512", "stack *", "heap 8K", "statics", ".noinit", "stack:core1 1K"]"#,
+            )
+            .unwrap();
+        assert_eq!(
+            config.layout,
+            [
+                LayoutItem::Reserved(64),
+                LayoutItem::FaultStack(512),
+                LayoutItem::Stack(None),
+                LayoutItem::Heap(8192),
+                LayoutItem::Statics,
+                LayoutItem::Section(".noinit".to_string()),
+                LayoutItem::ExtraStack(ExtraStack {
+                    name: "core1".to_string(),
+                    size: 1024
+                }),
+            ]
+        );
+        assert_eq!(config.below_stack(), Some(LayoutItem::FaultStack(512)));
+        assert_eq!(config.check_layout(), Ok(()));
+
+        assert!(LayoutItem::parse("statics 1K").is_err());
+        assert!(LayoutItem::parse("heap").is_err());
+        assert!(LayoutItem::parse("bss 1K").is_err());
+
+        let layout = |items: &[&str]| Config {
+            layout: items
+                .iter()
+                .map(|item| LayoutItem::parse(item).unwrap())
+                .collect(),
+            ..Config::default()
+        };
+        assert!(layout(&["guard 256", "canary 32", "stack *", "statics"])
+            .check_layout()
+            .is_ok());
+        assert!(layout(&["stack *", "statics", "stack 1K"])
+            .check_layout()
+            .is_err());
+        assert!(layout(&["canary 32", "statics", "stack *"])
+            .check_layout()
+            .is_err());
+        assert!(layout(&["stack *"]).check_layout().is_err());
+        assert_eq!(
+            layout(&["stack *", "reserved 4", "statics"]).check_layout(),
+            Err("a reserved area can only be the first or the last item of the layout".to_string())
+        );
+        assert!(Config {
+            heap_size: Some(1024),
+            ..layout(&["stack *", "statics"])
+        }
+        .check_layout()
+        .is_err());
+    }
+
+    #[test]
+    fn strip_arguments() {
+        let args = [
+            "-flavor",
+            "gnu",
+            "--flip-link-mpu-guard=256",
+            "--flip-link-strict",
+            "--flip-link-extra-stacks=core1:4K",
+            "--flip-link-extra-stacks=core2:2K",
+            "-Tlink.x",
+        ]
+        .map(String::from);
+
+        let mut config = Config::default();
+        let linker_args = config.parse_args(&args).unwrap();
+        assert_eq!(config.mpu_guard, Some(256));
+        assert!(config.strict);
+        assert_eq!(config.extra_stacks.len(), 2);
+        assert_eq!(config.extra_stacks[1].size, 2048);
+        assert_eq!(linker_args, ["-flavor", "gnu", "-Tlink.x"]);
+    }
+}
@@ -0,0 +1,1411 @@
+use std::{
+    fmt::{self, Write as _},
+    fs,
+    ops::Range,
+    path::Path,
+};
+
+use crate::{
+    arch::Arch,
+    arithmetic,
+    config::{Config, ExtraStack, HeapPlacement, LayoutItem},
+    Result,
+};
+
+/// Smallest region the ARMv7-M MPU supports (the ARMv6-M one needs at least 256 bytes)
+const MPU_MIN_REGION_SIZE: u64 = 32;
+/// Alignment of the start and end of the heap
+const HEAP_ALIGN: u64 = 8;
+/// The canary is made of whole words
+const CANARY_WORD: u64 = 4;
+/// MSPLIM and PSPLIM ignore the lowest 3 bits
+const STACK_LIMIT_ALIGN: u64 = 8;
+/// Size of the seal at the top of the stacks of TrustZone secure images: two words of
+/// `0xFEF5EDA5`
+const STACK_SEAL_SIZE: u64 = 8;
+/// Runtimes paint the stack a word at a time
+const PAINT_WORD: u64 = 4;
+/// Default gap above each task stack section: an ARMv7-M exception frame without FPU state
+const DEFAULT_TASK_STACK_GAP: u64 = 32;
+
+/// Symbols that only `flip-link` defines
+///
+/// The program may refer to them, so they also need a (placeholder) value in the first link.
+pub fn placeholder_symbols(config: &Config) -> Vec<String> {
+    let mut symbols = [
+        "__flip_link_guard_start",
+        "__flip_link_guard_end",
+        "__eheap",
+        "_heap_size",
+        "__flip_link_fault_stack_start",
+        "__flip_link_fault_stack_end",
+        "_stack_limit",
+        "__flip_link_stack_seal",
+        "__flip_link_canary_start",
+        "__flip_link_canary_end",
+        "__flip_link_reserved_top_start",
+        "__flip_link_reserved_top_end",
+        "__flip_link_reserved_bottom_start",
+        "__flip_link_reserved_bottom_end",
+    ]
+    .map(String::from)
+    .to_vec();
+    let layout_stacks = config.layout.iter().filter_map(|item| match item {
+        LayoutItem::ExtraStack(stack) => Some(stack),
+        _ => None,
+    });
+    for stack in config.extra_stacks.iter().chain(layout_stacks) {
+        symbols.push(format!("_{}_stack_start", stack.name));
+        symbols.push(format!("_{}_stack_end", stack.name));
+    }
+    symbols
+}
+
+/// The flipped memory layout
+#[derive(Debug, PartialEq)]
+pub struct Layout {
+    /// The fake RAM region the statics get linked into
+    pub ram: Range<u64>,
+    /// The stack grows from `stack.end` (`_stack_start`) downwards to `stack.start` (`_stack_end`)
+    pub stack: Range<u64>,
+    /// MPU guard band below the stack and its canary, above the task stacks
+    pub guard: Option<Range<u64>>,
+    /// Heap, either between the stack and the statics or below the stack
+    pub heap: Option<Range<u64>>,
+    /// Named stacks between the main stack and the heap or the statics, from the top down
+    pub extra_stacks: Vec<(String, Range<u64>)>,
+    /// Stack for the fault handler
+    pub fault_stack: Option<Range<u64>>,
+    /// Value for the MSPLIM register of ARMv8-M, the (aligned) bottom of the stack
+    pub stack_limit: Option<u64>,
+    /// Stack seal of TrustZone secure images, right above the stack
+    pub seal: Option<Range<u64>>,
+    /// Canary block at the bottom of the stack, which the runtime fills and checks
+    pub canary: Option<Range<u64>>,
+    /// Sections at fixed offsets below the end of RAM, above the statics
+    pub pinned: Vec<(String, Range<u64>)>,
+    /// Reserved bytes at the end of RAM, above everything else
+    pub reserved_top: Option<Range<u64>>,
+    /// Reserved bytes at the start of RAM, below everything else
+    pub reserved_bottom: Option<Range<u64>>,
+    /// Sections of task stacks below the main stack, each with a gap above it, from the bottom up
+    pub task_stacks: Vec<(String, Range<u64>)>,
+    /// Output section that reserves the main stack, at the bottom of the stack
+    pub stack_section: Option<(String, Range<u64>)>,
+}
+
+/// Memory regions besides RAM that parts of the layout go into, instead of RAM, and the task
+/// stack sections the first link found
+#[derive(Debug, Default)]
+pub struct Regions {
+    pub stack: Option<Range<u64>>,
+    pub fault_stack: Option<Range<u64>>,
+    pub task_stacks: Vec<OutputSection>,
+}
+
+/// An output section that flip-link moves, e.g. one of task stacks of an RTOS or an async executor
+#[derive(Debug)]
+pub struct OutputSection {
+    pub name: String,
+    pub size: u64,
+    pub align: u64,
+}
+
+impl Layout {
+    /// Pushes `used_ram_length` bytes of statics all the way to the end of `ram` and gives the
+    /// rest of it to the stack; or all of `regions.stack`, if the stack goes into a region of its own
+    pub fn new(
+        ram: Range<u64>,
+        regions: &Regions,
+        used_ram_length: u64,
+        used_ram_align: u64,
+        arch: Arch,
+        config: &Config,
+    ) -> Result<Self> {
+        // the reserved bytes at either end belong to someone else, e.g. a bootloader; the layout
+        // only uses what is in between
+        let full_ram = ram;
+        let reserved_top = config
+            .reserved_top
+            .map(|size| -> Result<_> {
+                let start = arithmetic::sub(full_ram.end, size, "end of RAM - reserved-top")?;
+                Ok(start..full_ram.end)
+            })
+            .transpose()?;
+        let reserved_bottom = config
+            .reserved_bottom
+            .map(|size| -> Result<_> {
+                let end = arithmetic::add(full_ram.start, size, "ORIGIN(RAM) + reserved-bottom")?;
+                Ok(full_ram.start..end)
+            })
+            .transpose()?;
+        let ram = reserved_bottom.as_ref().map_or(full_ram.start, |r| r.end)
+            ..reserved_top.as_ref().map_or(full_ram.end, |r| r.start);
+        if ram.start > ram.end {
+            return Err(format!(
+                "reserved-bottom ({} bytes) and reserved-top ({} bytes) do not fit into RAM \
+                ({:#x}..{:#x})",
+                config.reserved_bottom.unwrap_or(0),
+                config.reserved_top.unwrap_or(0),
+                full_ram.start,
+                full_ram.end
+            )
+            .into());
+        }
+
+        // the pinned sections keep the top of RAM to themselves
+        let pinned_area = config
+            .pinned_sections
+            .iter()
+            .map(|(_, offset)| *offset)
+            .max()
+            .unwrap_or(0);
+        let statics_end = arithmetic::sub(ram.end, pinned_area, "end of RAM - pinned sections")?;
+        let (statics_length, statics_align) = statics_extent(used_ram_length, used_ram_align, arch);
+        let new_origin = round_down_to_nearest_multiple(
+            arithmetic::sub(
+                statics_end,
+                statics_length,
+                "ORIGIN(RAM) + LENGTH(RAM) - used RAM",
+            )?,
+            statics_align,
+        );
+
+        // RAM that is neither used by the statics nor by the heap
+        let mut free_ram = ram.start..new_origin;
+
+        let heap = config
+            .heap_size
+            .map(|size| place_heap(&mut free_ram, size, config.heap_placement))
+            .transpose()?;
+
+        // the other stacks go right below the statics (and the heap), so that they cannot
+        // overflow into them either; the main stack keeps the bottom of RAM to itself
+        let extra_stacks = config
+            .extra_stacks
+            .iter()
+            .map(|stack| {
+                let range = place_stack(&mut free_ram, stack.size, arch.sp_align())
+                    .map_err(|e| format!("{e} for the {} stack", stack.name))?;
+                Ok((stack.name.clone(), range))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // an overflow of the main stack must not run into the fault stack, so the fault stack only
+        // goes to the bottom of RAM if an MPU guard band separates the two, and no task stacks
+        // would overflow into it; else it goes above the main stack
+        let fault_stack = match (config.fault_stack, &regions.fault_stack) {
+            (None, _) => None,
+            (Some(size), Some(region)) => {
+                check_no_overlap("fault stack", region, &full_ram)?;
+                Some(place_stack(&mut region.clone(), size, arch.sp_align())?)
+            }
+            (Some(size), None)
+                if config.mpu_guard.is_some()
+                    && regions.stack.is_none()
+                    && regions.task_stacks.is_empty() =>
+            {
+                let start = arithmetic::align_up(free_ram.start, arch.sp_align(), "fault stack")?;
+                let end = arithmetic::add(start, size, "fault stack")?;
+                if end > free_ram.end {
+                    return Err(format!(
+                        "not enough RAM for a fault stack of {size} bytes: only {:#x}..{:#x} is \
+                        free",
+                        free_ram.start, free_ram.end
+                    )
+                    .into());
+                }
+                free_ram.start = end;
+                Some(start..end)
+            }
+            (Some(size), None) => Some(
+                place_stack(&mut free_ram, size, arch.sp_align())
+                    .map_err(|e| format!("{e} for the fault stack"))?,
+            ),
+        };
+
+        let (mut stack_end, stack_start) = match &regions.stack {
+            Some(region) => {
+                check_no_overlap("stack", region, &full_ram)?;
+                if let Some(fault_stack) = &fault_stack {
+                    check_no_overlap("stack", region, fault_stack)?;
+                }
+                (
+                    region.start,
+                    round_down_to_nearest_multiple(region.end, arch.sp_align()),
+                )
+            }
+            None => (
+                free_ram.start,
+                round_down_to_nearest_multiple(free_ram.end, arch.sp_align()),
+            ),
+        };
+
+        // task stacks overflow downwards like the main stack does, so they go below it, where
+        // the bottom of RAM is below the lowest one and a gap below the others
+        let mut task_stacks = vec![];
+        for section in &regions.task_stacks {
+            let what = format!("task stack section {}", section.name);
+            let start = arithmetic::align_up(stack_end, section.align.max(1), &what)?;
+            let end = arithmetic::add(start, section.size, &what)?;
+            let gap_end = arithmetic::add(
+                end,
+                config.task_stack_gap.unwrap_or(DEFAULT_TASK_STACK_GAP),
+                &format!("gap above {}", section.name),
+            )?;
+            stack_end = arithmetic::align_up(gap_end, arch.sp_align(), &what)?;
+            task_stacks.push((section.name.clone(), start..end));
+        }
+
+        // the guard band goes right below the main stack (and its canary), above the task stacks,
+        // so that an overflow of the main stack faults instead of running into them
+        let guard = config
+            .mpu_guard
+            .map(|size| mpu_guard(stack_end, size))
+            .transpose()?;
+        if let Some(guard) = &guard {
+            stack_end = guard.end;
+        }
+
+        // an overflow tramples the canary before it gets past the bottom of the stack
+        let canary = config
+            .canary
+            .map(|size| -> Result<_> {
+                check_canary_size(size)?;
+                let start = arithmetic::align_up(stack_end, CANARY_WORD, "canary")?;
+                let end = arithmetic::add(start, size, "canary")?;
+                stack_end = end;
+                Ok(start..end)
+            })
+            .transpose()?;
+
+        let mut stack = stack_end..stack_start;
+        let (seal, stack_limit) = armv8m_stack(&mut stack, arch)?;
+
+        Ok(Self {
+            ram: new_origin..ram.end,
+            stack,
+            guard,
+            heap,
+            extra_stacks,
+            fault_stack,
+            stack_limit,
+            seal,
+            canary,
+            pinned: vec![],
+            reserved_top,
+            reserved_bottom,
+            task_stacks,
+            stack_section: None,
+        })
+    }
+
+    /// Lays RAM out in the order of `config.layout`, from the bottom up
+    ///
+    /// The areas up to the `stack *` go to the bottom of `ram`, the ones after it to the top, and
+    /// the stack gets what is left in between. Without a `stack *` all areas go to the bottom.
+    /// `sections` are the output sections that the layout names, from the first link.
+    pub fn from_description(
+        ram: Range<u64>,
+        sections: &[OutputSection],
+        used_ram_length: u64,
+        used_ram_align: u64,
+        arch: Arch,
+        config: &Config,
+    ) -> Result<Self> {
+        let items = &config.layout;
+        let extent = |item: &LayoutItem| -> Result<(u64, u64)> {
+            Ok(match item {
+                LayoutItem::Stack(size) => (size.unwrap_or(0), arch.sp_align()),
+                LayoutItem::Statics => statics_extent(used_ram_length, used_ram_align, arch),
+                LayoutItem::Heap(size) => (*size, HEAP_ALIGN),
+                LayoutItem::ExtraStack(ExtraStack { size, .. }) | LayoutItem::FaultStack(size) => {
+                    (*size, arch.sp_align())
+                }
+                LayoutItem::Guard(size) => {
+                    check_mpu_guard_size(*size)?;
+                    (*size, *size)
+                }
+                LayoutItem::Canary(size) => {
+                    check_canary_size(*size)?;
+                    (*size, CANARY_WORD)
+                }
+                LayoutItem::Reserved(size) => (*size, 1),
+                LayoutItem::Section(name) => {
+                    let section = sections
+                        .iter()
+                        .find(|section| section.name == *name)
+                        .ok_or_else(|| format!("the layout names {name}, which is not in RAM"))?;
+                    (section.size, section.align.max(1))
+                }
+            })
+        };
+
+        let flexible = items
+            .iter()
+            .position(|item| *item == LayoutItem::Stack(None))
+            .unwrap_or(items.len());
+        let mut areas = vec![0..0; items.len()];
+
+        let mut bottom = ram.start;
+        for (index, item) in items.iter().enumerate().take(flexible) {
+            let (size, align) = extent(item)?;
+            let start = arithmetic::align_up(bottom, align, &format!("start of the {item}"))?;
+            bottom = arithmetic::add(start, size, &format!("end of the {item}"))?;
+            areas[index] = start..bottom;
+        }
+        let mut top = ram.end;
+        for (index, item) in items.iter().enumerate().skip(flexible + 1).rev() {
+            let (size, align) = extent(item)?;
+            top = round_down_to_nearest_multiple(
+                arithmetic::sub(top, size, &format!("start of the {item}"))?,
+                align,
+            );
+            areas[index] = top..top + size;
+        }
+        if flexible < items.len() {
+            let start = arithmetic::align_up(bottom, arch.sp_align(), "bottom of the stack")?;
+            areas[flexible] =
+                start..round_down_to_nearest_multiple(top, arch.sp_align()).max(start);
+            bottom = start;
+        }
+        if bottom > top || top < ram.start || bottom > ram.end {
+            return Err(format!(
+                "not enough RAM for the layout: its areas need {:#x}..{:#x}, but RAM is \
+                {:#x}..{:#x}",
+                areas
+                    .iter()
+                    .map(|area| area.start)
+                    .min()
+                    .unwrap_or(ram.start),
+                areas.iter().map(|area| area.end).max().unwrap_or(ram.end),
+                ram.start,
+                ram.end
+            )
+            .into());
+        }
+
+        let mut layout = Self {
+            ram: 0..0,
+            stack: 0..0,
+            guard: None,
+            heap: None,
+            extra_stacks: vec![],
+            fault_stack: None,
+            stack_limit: None,
+            seal: None,
+            canary: None,
+            pinned: vec![],
+            reserved_top: None,
+            reserved_bottom: None,
+            task_stacks: vec![],
+            stack_section: None,
+        };
+        for (index, (item, area)) in items.iter().zip(areas).enumerate() {
+            match item {
+                LayoutItem::Stack(_) => layout.stack = area,
+                LayoutItem::Statics => layout.ram = area,
+                LayoutItem::Heap(_) => layout.heap = Some(area),
+                LayoutItem::ExtraStack(stack) => {
+                    layout.extra_stacks.push((stack.name.clone(), area))
+                }
+                LayoutItem::FaultStack(_) => layout.fault_stack = Some(area),
+                LayoutItem::Guard(_) => layout.guard = Some(area),
+                LayoutItem::Canary(_) => layout.canary = Some(area),
+                LayoutItem::Reserved(_) if index == 0 => layout.reserved_bottom = Some(area),
+                LayoutItem::Reserved(_) => layout.reserved_top = Some(area),
+                LayoutItem::Section(name) => layout.pinned.push((name.clone(), area)),
+            }
+        }
+        (layout.seal, layout.stack_limit) = armv8m_stack(&mut layout.stack, arch)?;
+
+        // the linker reports sections above the end of the (fake) RAM region as overflowing it, so
+        // the region extends to the end of the last section above the statics
+        layout.ram.end = layout
+            .pinned
+            .iter()
+            .map(|(_, area)| area.end)
+            .filter(|&end| end > layout.ram.start)
+            .fold(layout.ram.end, u64::max);
+
+        Ok(layout)
+    }
+
+    /// Pins section `name`, of `size` bytes and aligned to `align`, at `offset` bytes below the end
+    /// of RAM
+    pub fn pin_section(&mut self, name: &str, offset: u64, size: u64, align: u64) -> Result<()> {
+        let start = arithmetic::sub(
+            self.ram.end,
+            offset,
+            &format!("end of RAM - offset of {name}"),
+        )?;
+        let end = arithmetic::add(start, size, &format!("address of {name} + its size"))?;
+        if end > self.ram.end {
+            return Err(format!(
+                "pinned section {name} ({size} bytes) does not fit into the {offset} bytes \
+                below the end of RAM"
+            )
+            .into());
+        }
+        if start % align.max(1) != 0 {
+            return Err(format!(
+                "pinned section {name} needs to be aligned to {align} bytes, but \
+                {start:#x} (end of RAM - {offset}) is not"
+            )
+            .into());
+        }
+        if let Some((other, _)) = self
+            .pinned
+            .iter()
+            .find(|(_, other)| start < other.end && other.start < end)
+        {
+            return Err(format!("pinned sections {name} and {other} overlap").into());
+        }
+
+        self.pinned.push((name.to_string(), start..end));
+        Ok(())
+    }
+
+    /// Moves the output section `name`, which reserves the stack, to the bottom of the stack
+    ///
+    /// With a `size`, the section has to fit into the stack; without one, it fills the stack,
+    /// like riscv-rt's `.stack`, which extends up to `_stack_start`.
+    pub fn place_stack_section(&mut self, name: &str, size: Option<u64>) -> Result<()> {
+        let available = self.stack.end - self.stack.start;
+        let size = size.unwrap_or(available);
+        if size > available {
+            return Err(format!(
+                "the {name} section reserves {size} bytes of stack, but only {available} bytes \
+                are left for the stack"
+            )
+            .into());
+        }
+
+        self.stack_section = Some((name.to_string(), self.stack.start..self.stack.start + size));
+        Ok(())
+    }
+
+    /// Checks that `painted`, the `_stack_end.._stack_start` of the final program that the runtime
+    /// paints at reset, is the flipped stack, and that it overlaps none of `sections`
+    pub fn check_painted_stack(
+        &self,
+        painted: &Range<u64>,
+        sections: &[(String, Range<u64>)],
+    ) -> Result<()> {
+        if *painted != self.stack {
+            return Err(format!(
+                "the program paints {:#x}..{:#x} at reset, but the flipped stack is {:#x}..{:#x}; \
+                does the linker script assign `_stack_start` or `_stack_end` without `PROVIDE`?",
+                painted.start, painted.end, self.stack.start, self.stack.end
+            )
+            .into());
+        }
+        if !painted.start.is_multiple_of(PAINT_WORD) || !painted.end.is_multiple_of(PAINT_WORD) {
+            return Err(format!(
+                "the stack ({:#x}..{:#x}) is painted a word at a time, but its bounds are not \
+                word-aligned",
+                painted.start, painted.end
+            )
+            .into());
+        }
+
+        // a section that reserves the stack is meant to be painted
+        let stack_section = self.stack_section.as_ref().map(|(name, _)| name);
+        if let Some((name, section)) = sections.iter().find(|(name, section)| {
+            Some(name) != stack_section
+                && section.start < painted.end
+                && painted.start < section.end
+        }) {
+            return Err(format!(
+                "painting the stack ({:#x}..{:#x}) at reset would overwrite {name} \
+                ({:#x}..{:#x})",
+                painted.start, painted.end, section.start, section.end
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Addresses of the pinned, the task stack and the stack sections, for `--section-start`
+    pub fn section_starts(&self) -> Vec<(String, u64)> {
+        self.pinned
+            .iter()
+            .chain(&self.task_stacks)
+            .chain(&self.stack_section)
+            .map(|(name, range)| (name.clone(), range.start))
+            .collect()
+    }
+
+    /// Symbols to define in the final link
+    pub fn symbols(&self) -> Vec<(String, u64)> {
+        let mut symbols = vec![
+            ("_stack_start".to_string(), self.stack.end),
+            ("_stack_end".to_string(), self.stack.start),
+        ];
+        if let Some(guard) = &self.guard {
+            symbols.push(("__flip_link_guard_start".to_string(), guard.start));
+            symbols.push(("__flip_link_guard_end".to_string(), guard.end));
+        }
+        if let Some(heap) = &self.heap {
+            // `cortex-m-rt` `PROVIDE`s `__sheap` right after the statics; that would now be the
+            // very end of RAM
+            symbols.push(("__sheap".to_string(), heap.start));
+            symbols.push(("__eheap".to_string(), heap.end));
+            symbols.push(("_heap_size".to_string(), heap.end - heap.start));
+        }
+        for (name, stack) in &self.extra_stacks {
+            symbols.push((format!("_{name}_stack_start"), stack.end));
+            symbols.push((format!("_{name}_stack_end"), stack.start));
+        }
+        if let Some(fault_stack) = &self.fault_stack {
+            symbols.push(("__flip_link_fault_stack_start".to_string(), fault_stack.end));
+            symbols.push(("__flip_link_fault_stack_end".to_string(), fault_stack.start));
+        }
+        if let Some(stack_limit) = self.stack_limit {
+            symbols.push(("_stack_limit".to_string(), stack_limit));
+        }
+        if let Some(seal) = &self.seal {
+            symbols.push(("__flip_link_stack_seal".to_string(), seal.start));
+        }
+        if let Some(canary) = &self.canary {
+            symbols.push(("__flip_link_canary_start".to_string(), canary.start));
+            symbols.push(("__flip_link_canary_end".to_string(), canary.end));
+        }
+        if let Some(reserved) = &self.reserved_top {
+            symbols.push(("__flip_link_reserved_top_start".to_string(), reserved.start));
+            symbols.push(("__flip_link_reserved_top_end".to_string(), reserved.end));
+        }
+        if let Some(reserved) = &self.reserved_bottom {
+            symbols.push((
+                "__flip_link_reserved_bottom_start".to_string(),
+                reserved.start,
+            ));
+            symbols.push(("__flip_link_reserved_bottom_end".to_string(), reserved.end));
+        }
+        symbols
+    }
+
+    /// Fails if the main stack is smaller than `required` bytes
+    pub fn check_stack_size(&self, required: u64, used_ram_length: u64) -> Result<()> {
+        let available = self.stack.end - self.stack.start;
+        if available < required {
+            return Err(format!(
+                "statics use {used_ram_length} bytes, only {available} bytes left for stack, \
+                {required} required"
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// All areas of the layout, from the highest address downwards
+    fn areas(&self) -> Vec<(String, &Range<u64>)> {
+        let mut areas = vec![
+            ("statics".to_string(), &self.ram),
+            ("stack".to_string(), &self.stack),
+        ];
+        areas.extend(
+            self.guard
+                .iter()
+                .map(|guard| ("MPU guard".to_string(), guard)),
+        );
+        areas.extend(self.heap.iter().map(|heap| ("heap".to_string(), heap)));
+        areas.extend(
+            self.seal
+                .iter()
+                .map(|seal| ("stack seal".to_string(), seal)),
+        );
+        areas.extend(
+            self.canary
+                .iter()
+                .map(|canary| ("canary".to_string(), canary)),
+        );
+        areas.extend(
+            self.pinned
+                .iter()
+                .map(|(name, range)| (name.clone(), range)),
+        );
+        areas.extend(
+            self.stack_section
+                .iter()
+                .map(|(name, range)| (name.clone(), range)),
+        );
+        areas.extend(
+            self.task_stacks
+                .iter()
+                .map(|(name, range)| (name.clone(), range)),
+        );
+        areas.extend(
+            self.fault_stack
+                .iter()
+                .map(|fault_stack| ("fault stack".to_string(), fault_stack)),
+        );
+        areas.extend(
+            self.extra_stacks
+                .iter()
+                .map(|(name, stack)| (format!("{name} stack"), stack)),
+        );
+        areas.extend(
+            self.reserved_top
+                .iter()
+                .chain(&self.reserved_bottom)
+                .map(|reserved| ("reserved".to_string(), reserved)),
+        );
+        areas.sort_by_key(|(_, range)| std::cmp::Reverse(range.start));
+        areas
+    }
+}
+
+/// The layout report, one area per line
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, range) in self.areas() {
+            writeln!(
+                f,
+                "  {name:<16} {:#010x}..{:#010x} {:>8} bytes",
+                range.start,
+                range.end,
+                range.end - range.start
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors if the `what` region overlaps `other`
+fn check_no_overlap(what: &str, region: &Range<u64>, other: &Range<u64>) -> Result<()> {
+    if region.start < other.end && other.start < region.end {
+        return Err(format!(
+            "the {what} region ({:#x}..{:#x}) overlaps {:#x}..{:#x}",
+            region.start, region.end, other.start, other.end
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Carves the stack seal of TrustZone secure images off the top of `stack`, and aligns the
+/// bottom of `stack` for the MSPLIM register of ARMv8-M
+///
+/// Returns the seal and the stack limit.
+fn armv8m_stack(stack: &mut Range<u64>, arch: Arch) -> Result<(Option<Range<u64>>, Option<u64>)> {
+    // secure code must not return to a forged exception frame at the top of its stack; the seal
+    // value there makes such a return fault
+    let seal = if arch.is_secure() {
+        let seal_end = stack.end;
+        stack.end = arithmetic::sub(stack.end, STACK_SEAL_SIZE, "stack seal")?;
+        Some(stack.end..seal_end)
+    } else {
+        None
+    };
+
+    let stack_limit = if arch.has_stack_limit() {
+        stack.start = arithmetic::align_up(stack.start, STACK_LIMIT_ALIGN, "_stack_limit")?;
+        Some(stack.start)
+    } else {
+        None
+    };
+
+    if stack.start > stack.end {
+        return Err(format!(
+            "not enough RAM: the stack would end at {:#x}, above its start at {:#x}",
+            stack.start, stack.end
+        )
+        .into());
+    }
+
+    Ok((seal, stack_limit))
+}
+
+fn check_canary_size(size: u64) -> Result<()> {
+    if size == 0 || !size.is_multiple_of(CANARY_WORD) {
+        return Err(
+            format!("the canary must be a multiple of {CANARY_WORD} bytes, not {size}").into(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Length and alignment of the fake RAM region for `used_ram_length` bytes of statics
+///
+/// The region is never shorter than its alignment, even if the program has no statics: the linker
+/// script still places (empty) `.data`, `.bss` and `.uninit` sections into RAM, aligns the location
+/// counter in between and may assert that the sections lie within RAM, which a region of
+/// `LENGTH = 0` makes fail.
+fn statics_extent(used_ram_length: u64, used_ram_align: u64, arch: Arch) -> (u64, u64) {
+    let align = used_ram_align.max(arch.sp_align());
+    (used_ram_length.max(align), align)
+}
+
+fn round_down_to_nearest_multiple(x: u64, multiple: u64) -> u64 {
+    x - (x % multiple)
+}
+
+/// Places a heap of `size` bytes at the top or the bottom of `free_ram`, depending on `placement`,
+/// and removes it from `free_ram`
+fn place_heap(
+    free_ram: &mut Range<u64>,
+    size: u64,
+    placement: HeapPlacement,
+) -> Result<Range<u64>> {
+    let start = match placement {
+        HeapPlacement::AboveStack => free_ram
+            .end
+            .checked_sub(size)
+            .map(|start| round_down_to_nearest_multiple(start, HEAP_ALIGN)),
+        HeapPlacement::BelowStack => free_ram.start.checked_next_multiple_of(HEAP_ALIGN),
+    };
+    let heap = start
+        .and_then(|start| Some(start..start.checked_add(size)?))
+        .filter(|heap| free_ram.start <= heap.start && heap.end <= free_ram.end)
+        .ok_or_else(|| {
+            format!(
+                "not enough RAM for a heap of {size} bytes: only {:#x}..{:#x} is free",
+                free_ram.start, free_ram.end
+            )
+        })?;
+
+    match placement {
+        HeapPlacement::AboveStack => free_ram.end = heap.start,
+        HeapPlacement::BelowStack => free_ram.start = heap.end,
+    }
+
+    Ok(heap)
+}
+
+/// Places a stack of `size` bytes at the top of `free_ram` and removes it from `free_ram`
+fn place_stack(free_ram: &mut Range<u64>, size: u64, sp_align: u64) -> Result<Range<u64>> {
+    let start = round_down_to_nearest_multiple(free_ram.end, sp_align);
+    let end = start
+        .checked_sub(size)
+        .map(|end| round_down_to_nearest_multiple(end, sp_align))
+        .filter(|&end| end >= free_ram.start)
+        .ok_or_else(|| {
+            format!(
+                "not enough RAM for {size} bytes of stack: only {:#x}..{:#x} is free",
+                free_ram.start, free_ram.end
+            )
+        })?;
+
+    free_ram.end = end;
+    Ok(end..start)
+}
+
+/// Places an MPU guard band of `size` bytes at or above `bottom`
+///
+/// MPU regions must be a power of two in size and aligned to their size.
+fn mpu_guard(bottom: u64, size: u64) -> Result<Range<u64>> {
+    check_mpu_guard_size(size)?;
+
+    let start = arithmetic::align_up(bottom, size, "bottom of the MPU guard band")?;
+    let end = arithmetic::add(start, size, "MPU guard band")?;
+    Ok(start..end)
+}
+
+fn check_mpu_guard_size(size: u64) -> Result<()> {
+    if !size.is_power_of_two() || size < MPU_MIN_REGION_SIZE {
+        return Err(format!(
+            "the MPU guard band must be a power of two of at least {MPU_MIN_REGION_SIZE} bytes, \
+            not {size}"
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Writes the MPU region settings for `guard` as Rust source code that firmware can `include!`
+///
+/// The file is only written if its contents change, so it does not trigger needless rebuilds.
+pub fn write_mpu_guard_file(path: &Path, guard: &Range<u64>, arch: Arch) -> Result<()> {
+    let contents = mpu_guard_settings(guard, arch)?;
+    if fs::read_to_string(path).ok().as_deref() != Some(&contents) {
+        log::info!("writing MPU settings to {}", path.display());
+        fs::write(path, contents)?;
+    }
+
+    Ok(())
+}
+
+/// The MPU region settings for `guard`, in the register format of the MPU of `arch`
+fn mpu_guard_settings(guard: &Range<u64>, arch: Arch) -> Result<String> {
+    if !arch.is_arm() {
+        return Err("the MPU settings for the guard band can only be generated for ARM".into());
+    }
+
+    let size = guard.end - guard.start;
+    let start = u32::try_from(guard.start).map_err(|_| "MPU guard band above 4 GiB")?;
+
+    let mut contents = String::new();
+    writeln!(
+        contents,
+        "// MPU settings for the stack guard band; generated by flip-link, do not edit"
+    )?;
+    writeln!(contents)?;
+    writeln!(contents, "/// Lowest address of the guard band")?;
+    writeln!(contents, "pub const GUARD_START: u32 = {start:#010x};")?;
+    writeln!(contents, "/// Size of the guard band in bytes")?;
+    writeln!(contents, "pub const GUARD_SIZE: u32 = {size};")?;
+
+    if arch.has_stack_limit() {
+        // the ARMv8-M MPU has no "no access" permission; read-only is enough to stop a stack
+        // that pushes into the guard band
+        // MPU_RBAR: BASE, SH = non-shareable, AP = read-only by privileged code, XN
+        let rbar = start | 0b10 << 1 | 1;
+        // MPU_RLAR: LIMIT = the last 32-byte block, AttrIndx = 0, EN
+        let last = u32::try_from(guard.end - 1).map_err(|_| "MPU guard band above 4 GiB")?;
+        let rlar = last & !0x1f | 1;
+
+        writeln!(
+            contents,
+            "/// `MPU_RBAR` value: read-only, privileged only, execute never; select the region \
+            with `MPU_RNR` first"
+        )?;
+        writeln!(contents, "pub const MPU_RBAR: u32 = {rbar:#010x};")?;
+        writeln!(
+            contents,
+            "/// `MPU_RLAR` value: memory attributes from `MPU_MAIR0` index 0, region enabled"
+        )?;
+        writeln!(contents, "pub const MPU_RLAR: u32 = {rlar:#010x};")?;
+    } else {
+        // MPU_RASR: XN, AP = no access, SIZE = log2(size) - 1, ENABLE
+        let rasr = 1 << 28 | (size.trailing_zeros() - 1) << 1 | 1;
+
+        writeln!(
+            contents,
+            "/// `MPU_RBAR` value; select the region with `MPU_RNR` first"
+        )?;
+        writeln!(contents, "pub const MPU_RBAR: u32 = {start:#010x};")?;
+        writeln!(
+            contents,
+            "/// `MPU_RASR` value: no access, execute never, region enabled"
+        )?;
+        writeln!(contents, "pub const MPU_RASR: u32 = {rasr:#010x};")?;
+    }
+
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use object::Architecture;
+
+    use super::*;
+    use crate::config::ExtraStack;
+
+    const RAM: Range<u64> = 0x2000_0000..0x2001_0000;
+
+    #[test]
+    fn flip() {
+        let layout = Layout::new(
+            RAM,
+            &Regions::default(),
+            12,
+            4,
+            Arch::new(Architecture::Arm),
+            &Config::default(),
+        );
+
+        assert_eq!(
+            layout.unwrap(),
+            Layout {
+                ram: 0x2000_fff0..0x2001_0000,
+                stack: 0x2000_0000..0x2000_fff0,
+                guard: None,
+                heap: None,
+                extra_stacks: vec![],
+                fault_stack: None,
+                stack_limit: None,
+                seal: None,
+                canary: None,
+                pinned: vec![],
+                reserved_top: None,
+                reserved_bottom: None,
+                task_stacks: vec![],
+                stack_section: None,
+            }
+        );
+    }
+
+    #[test]
+    fn mpu_guard_band() {
+        let config = Config {
+            mpu_guard: Some(256),
+            ..Config::default()
+        };
+        let ram = 0x2000_0010..0x2001_0000;
+
+        let layout = Layout::new(
+            ram,
+            &Regions::default(),
+            12,
+            4,
+            Arch::new(Architecture::Arm),
+            &config,
+        )
+        .unwrap();
+        // aligned to its size
+        assert_eq!(layout.guard, Some(0x2000_0100..0x2000_0200));
+        assert_eq!(layout.stack, 0x2000_0200..0x2000_fff0);
+        assert!(layout
+            .symbols()
+            .contains(&("__flip_link_guard_start".to_string(), 0x2000_0100)));
+
+        let config = Config {
+            mpu_guard: Some(100),
+            ..Config::default()
+        };
+        assert!(Layout::new(
+            RAM,
+            &Regions::default(),
+            12,
+            4,
+            Arch::new(Architecture::Arm),
+            &config
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn separate_stack_region() {
+        let arm = Arch::new(Architecture::Arm);
+        let ccmram = 0x1000_0000..0x1001_0000;
+
+        let regions = Regions {
+            stack: Some(ccmram.clone()),
+            ..Regions::default()
+        };
+        let layout = Layout::new(RAM, &regions, 12, 4, arm, &Config::default());
+        assert_eq!(
+            layout.unwrap(),
+            Layout {
+                // the statics are still flipped within RAM
+                ram: 0x2000_fff0..0x2001_0000,
+                stack: ccmram,
+                guard: None,
+                heap: None,
+                extra_stacks: vec![],
+                fault_stack: None,
+                stack_limit: None,
+                seal: None,
+                canary: None,
+                pinned: vec![],
+                reserved_top: None,
+                reserved_bottom: None,
+                task_stacks: vec![],
+                stack_section: None,
+            }
+        );
+
+        // the regions must not overlap
+        let overlapping = 0x2000_8000..0x2001_8000;
+        let regions = Regions {
+            stack: Some(overlapping),
+            ..Regions::default()
+        };
+        assert!(Layout::new(RAM, &regions, 12, 4, arm, &Config::default()).is_err());
+    }
+
+    #[test]
+    fn heap() {
+        let arm = Arch::new(Architecture::Arm);
+        let mut config = Config {
+            heap_size: Some(0x1000),
+            ..Config::default()
+        };
+
+        // stack -> heap -> statics
+        let layout = Layout::new(RAM, &Regions::default(), 12, 4, arm, &config).unwrap();
+        assert_eq!(layout.heap, Some(0x2000_eff0..0x2000_fff0));
+        assert_eq!(layout.stack, 0x2000_0000..0x2000_eff0);
+        assert!(layout
+            .symbols()
+            .contains(&("__sheap".to_string(), 0x2000_eff0)));
+        assert!(layout
+            .symbols()
+            .contains(&("_heap_size".to_string(), 0x1000)));
+
+        // heap -> stack -> statics
+        config.heap_placement = HeapPlacement::BelowStack;
+        let layout = Layout::new(RAM, &Regions::default(), 12, 4, arm, &config).unwrap();
+        assert_eq!(layout.heap, Some(0x2000_0000..0x2000_1000));
+        assert_eq!(layout.stack, 0x2000_1000..0x2000_fff0);
+
+        // the heap must fit into RAM next to the statics
+        config.heap_size = Some(0x1_0000);
+        assert!(Layout::new(RAM, &Regions::default(), 12, 4, arm, &config).is_err());
+    }
+
+    #[test]
+    fn extra_stacks() {
+        let config = Config {
+            heap_size: Some(0x1000),
+            extra_stacks: vec![
+                ExtraStack {
+                    name: "core1".to_string(),
+                    size: 0x800,
+                },
+                ExtraStack {
+                    name: "irq".to_string(),
+                    size: 0x100,
+                },
+            ],
+            ..Config::default()
+        };
+
+        let layout = Layout::new(
+            RAM,
+            &Regions::default(),
+            12,
+            4,
+            Arch::new(Architecture::Arm),
+            &config,
+        )
+        .unwrap();
+        // main stack -> irq stack -> core1 stack -> heap -> statics
+        assert_eq!(layout.heap, Some(0x2000_eff0..0x2000_fff0));
+        assert_eq!(
+            layout.extra_stacks,
+            [
+                ("core1".to_string(), 0x2000_e7f0..0x2000_eff0),
+                ("irq".to_string(), 0x2000_e6f0..0x2000_e7f0),
+            ]
+        );
+        assert_eq!(layout.stack, 0x2000_0000..0x2000_e6f0);
+        assert!(layout
+            .symbols()
+            .contains(&("_core1_stack_start".to_string(), 0x2000_eff0)));
+
+        let report = layout.to_string();
+        let names = report
+            .lines()
+            .map(|line| line.split("  0x").next().unwrap().trim())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["statics", "heap", "core1 stack", "irq stack", "stack"]
+        );
+    }
+
+    #[test]
+    fn min_stack_size() {
+        let layout = Layout::new(
+            RAM,
+            &Regions::default(),
+            0xf000,
+            4,
+            Arch::new(Architecture::Arm),
+            &Config::default(),
+        )
+        .unwrap();
+
+        assert!(layout.check_stack_size(0x1000, 0xf000).is_ok());
+        assert_eq!(
+            layout
+                .check_stack_size(0x1001, 0xf000)
+                .unwrap_err()
+                .to_string(),
+            "statics use 61440 bytes, only 4096 bytes left for stack, 4097 required"
+        );
+    }
+
+    #[test]
+    fn fault_stack() {
+        let arm = Arch::new(Architecture::Arm);
+        let mut config = Config {
+            fault_stack: Some(0x200),
+            ..Config::default()
+        };
+
+        // stack -> fault stack -> statics: an overflow still hits the bottom of RAM
+        let layout = Layout::new(RAM, &Regions::default(), 12, 4, arm, &config).unwrap();
+        assert_eq!(layout.fault_stack, Some(0x2000_fdf0..0x2000_fff0));
+        assert_eq!(layout.stack, 0x2000_0000..0x2000_fdf0);
+        assert!(layout
+            .symbols()
+            .contains(&("__flip_link_fault_stack_start".to_string(), 0x2000_fff0)));
+
+        // fault stack -> MPU guard -> stack -> statics
+        config.mpu_guard = Some(0x100);
+        let layout = Layout::new(RAM, &Regions::default(), 12, 4, arm, &config).unwrap();
+        assert_eq!(layout.fault_stack, Some(0x2000_0000..0x2000_0200));
+        assert_eq!(layout.guard, Some(0x2000_0200..0x2000_0300));
+        assert_eq!(layout.stack, 0x2000_0300..0x2000_fff0);
+
+        // in a region of its own
+        let regions = Regions {
+            fault_stack: Some(0x1000_0000..0x1000_1000),
+            ..Regions::default()
+        };
+        let layout = Layout::new(RAM, &regions, 12, 4, arm, &config).unwrap();
+        assert_eq!(layout.fault_stack, Some(0x1000_0e00..0x1000_1000));
+    }
+
+    #[test]
+    fn armv8m() {
+        // RAM that starts at an address that is not 8-byte aligned
+        let ram = 0x2000_0004..0x2001_0000;
+        let layout = Layout::new(
+            ram.clone(),
+            &Regions::default(),
+            12,
+            4,
+            Arch::armv8m(false),
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(layout.stack_limit, Some(0x2000_0008));
+        assert_eq!(layout.stack, 0x2000_0008..0x2000_fff0);
+        assert_eq!(layout.seal, None);
+
+        // secure images get a stack seal above the stack
+        let layout = Layout::new(
+            ram,
+            &Regions::default(),
+            12,
+            4,
+            Arch::armv8m(true),
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(layout.seal, Some(0x2000_ffe8..0x2000_fff0));
+        assert_eq!(layout.stack, 0x2000_0008..0x2000_ffe8);
+        assert!(layout
+            .symbols()
+            .contains(&("__flip_link_stack_seal".to_string(), 0x2000_ffe8)));
+    }
+
+    #[test]
+    fn canary() {
+        let arm = Arch::new(Architecture::Arm);
+        let mut config = Config {
+            canary: Some(32),
+            ..Config::default()
+        };
+
+        let layout = Layout::new(RAM, &Regions::default(), 12, 4, arm, &config).unwrap();
+        assert_eq!(layout.canary, Some(0x2000_0000..0x2000_0020));
+        assert_eq!(layout.stack, 0x2000_0020..0x2000_fff0);
+        assert!(layout
+            .symbols()
+            .contains(&("__flip_link_canary_end".to_string(), 0x2000_0020)));
+
+        config.canary = Some(6);
+        assert!(Layout::new(RAM, &Regions::default(), 12, 4, arm, &config).is_err());
+    }
+
+    #[test]
+    fn pinned_sections() {
+        let arm = Arch::new(Architecture::Arm);
+        let config = Config {
+            pinned_sections: vec![
+                (".uninit".to_string(), 0x100),
+                (".noinit".to_string(), 0x40),
+            ],
+            ..Config::default()
+        };
+
+        // the statics end where the pinned sections begin, however large they are
+        let mut layout = Layout::new(RAM, &Regions::default(), 12, 4, arm, &config).unwrap();
+        assert_eq!(layout.ram, 0x2000_fef0..0x2001_0000);
+        assert_eq!(layout.stack, 0x2000_0000..0x2000_fef0);
+
+        layout.pin_section(".uninit", 0x100, 0x20, 4).unwrap();
+        layout.pin_section(".noinit", 0x40, 0x40, 4).unwrap();
+        assert_eq!(
+            layout.section_starts(),
+            [
+                (".uninit".to_string(), 0x2000_ff00),
+                (".noinit".to_string(), 0x2000_ffc0)
+            ]
+        );
+
+        // too large, overlapping and misaligned sections
+        assert!(layout.pin_section(".log", 0x10, 0x20, 4).is_err());
+        assert!(layout.pin_section(".log", 0x30, 0x20, 4).is_err());
+        assert!(layout.pin_section(".log", 0x84, 0x4, 8).is_err());
+    }
+
+    #[test]
+    fn reserved() {
+        let arm = Arch::new(Architecture::Arm);
+        let config = Config {
+            reserved_top: Some(0x100),
+            reserved_bottom: Some(4),
+            ..Config::default()
+        };
+
+        let layout = Layout::new(RAM, &Regions::default(), 12, 4, arm, &config).unwrap();
+        assert_eq!(layout.ram, 0x2000_fef0..0x2000_ff00);
+        assert_eq!(layout.stack, 0x2000_0004..0x2000_fef0);
+        assert_eq!(layout.reserved_top, Some(0x2000_ff00..0x2001_0000));
+        assert_eq!(layout.reserved_bottom, Some(0x2000_0000..0x2000_0004));
+        assert!(layout
+            .symbols()
+            .contains(&("__flip_link_reserved_bottom_end".to_string(), 0x2000_0004)));
+
+        let config = Config {
+            reserved_top: Some(0x8000),
+            reserved_bottom: Some(0x8004),
+            ..Config::default()
+        };
+        assert!(Layout::new(RAM, &Regions::default(), 0, 4, arm, &config).is_err());
+    }
+
+    #[test]
+    fn mpu_guard_file() {
+        let guard = 0x2000_0100..0x2000_0200;
+
+        let armv7m = mpu_guard_settings(&guard, Arch::new(Architecture::Arm)).unwrap();
+        assert!(armv7m.contains("pub const MPU_RBAR: u32 = 0x20000100;"));
+        assert!(armv7m.contains("pub const MPU_RASR: u32 = 0x1000000f;"));
+
+        let armv8m = mpu_guard_settings(&guard, Arch::armv8m(false)).unwrap();
+        assert!(armv8m.contains("pub const MPU_RBAR: u32 = 0x20000105;"));
+        assert!(armv8m.contains("pub const MPU_RLAR: u32 = 0x200001e1;"));
+        assert!(!armv8m.contains("MPU_RASR"));
+
+        assert!(mpu_guard_settings(&guard, Arch::new(Architecture::Riscv32)).is_err());
+    }
+
+    #[test]
+    fn task_stacks() {
+        let arm = Arch::new(Architecture::Arm);
+        let regions = Regions {
+            task_stacks: vec![
+                OutputSection {
+                    name: ".task_stacks".to_string(),
+                    size: 0x1000,
+                    align: 8,
+                },
+                OutputSection {
+                    name: ".embassy_arena".to_string(),
+                    size: 0x404,
+                    align: 16,
+                },
+            ],
+            ..Regions::default()
+        };
+
+        let layout = Layout::new(RAM, &regions, 12, 4, arm, &Config::default()).unwrap();
+        assert_eq!(
+            layout.task_stacks,
+            [
+                (".task_stacks".to_string(), 0x2000_0000..0x2000_1000),
+                (".embassy_arena".to_string(), 0x2000_1020..0x2000_1424)
+            ]
+        );
+        // the main stack starts above the gap over the last task stack section
+        assert_eq!(layout.stack, 0x2000_1448..0x2000_fff0);
+
+        // the guard band separates the main stack from the task stacks
+        let config = Config {
+            mpu_guard: Some(0x100),
+            canary: Some(32),
+            ..Config::default()
+        };
+        let layout = Layout::new(RAM, &regions, 12, 4, arm, &config).unwrap();
+        assert_eq!(layout.task_stacks[1].1, 0x2000_1020..0x2000_1424);
+        assert_eq!(layout.guard, Some(0x2000_1500..0x2000_1600));
+        assert_eq!(layout.canary, Some(0x2000_1600..0x2000_1620));
+        assert_eq!(layout.stack, 0x2000_1620..0x2000_fff0);
+        assert_eq!(
+            layout.section_starts(),
+            [
+                (".task_stacks".to_string(), 0x2000_0000),
+                (".embassy_arena".to_string(), 0x2000_1020)
+            ]
+        );
+    }
+
+    #[test]
+    fn description() {
+        let arm = Arch::new(Architecture::Arm);
+        let config = Config {
+            layout: vec![
+                LayoutItem::Reserved(64),
+                LayoutItem::FaultStack(512),
+                LayoutItem::Stack(None),
+                LayoutItem::Heap(0x2000),
+                LayoutItem::Statics,
+                LayoutItem::Section(".noinit".to_string()),
+            ],
+            ..Config::default()
+        };
+        let sections = [OutputSection {
+            name: ".noinit".to_string(),
+            size: 0x20,
+            align: 4,
+        }];
+
+        let layout = Layout::from_description(RAM, &sections, 12, 4, arm, &config).unwrap();
+        assert_eq!(layout.reserved_bottom, Some(0x2000_0000..0x2000_0040));
+        assert_eq!(layout.fault_stack, Some(0x2000_0040..0x2000_0240));
+        assert_eq!(layout.stack, 0x2000_0240..0x2000_dfd0);
+        assert_eq!(layout.heap, Some(0x2000_dfd0..0x2000_ffd0));
+        // the fake RAM region takes in the pinned section
+        assert_eq!(layout.ram, 0x2000_ffd0..0x2001_0000);
+        assert_eq!(
+            layout.pinned,
+            [(".noinit".to_string(), 0x2000_ffe0..0x2001_0000)]
+        );
+
+        // the section is not in RAM
+        assert!(Layout::from_description(RAM, &[], 12, 4, arm, &config).is_err());
+
+        let config = Config {
+            layout: vec![
+                LayoutItem::Stack(None),
+                LayoutItem::Heap(0x20000),
+                LayoutItem::Statics,
+            ],
+            ..Config::default()
+        };
+        assert!(Layout::from_description(RAM, &[], 12, 4, arm, &config).is_err());
+    }
+
+    #[test]
+    fn stack_section() {
+        let arm = Arch::new(Architecture::Arm);
+        let mut layout =
+            Layout::new(RAM, &Regions::default(), 12, 4, arm, &Config::default()).unwrap();
+
+        layout.place_stack_section(".stack", Some(0x800)).unwrap();
+        assert_eq!(
+            layout.stack_section,
+            Some((".stack".to_string(), 0x2000_0000..0x2000_0800))
+        );
+        assert_eq!(
+            layout.section_starts(),
+            [(".stack".to_string(), 0x2000_0000)]
+        );
+
+        // riscv-rt's `.stack` fills the stack
+        layout.place_stack_section(".stack", None).unwrap();
+        assert_eq!(layout.stack_section.as_ref().unwrap().1, layout.stack);
+
+        assert!(layout.place_stack_section(".stack", Some(0x10000)).is_err());
+    }
+
+    #[test]
+    fn painted_stack() {
+        let arm = Arch::new(Architecture::Arm);
+        let layout = Layout::new(RAM, &Regions::default(), 12, 4, arm, &Config::default()).unwrap();
+        let sections = [(".bss".to_string(), 0x2000_fff0..0x2000_fffc)];
+
+        assert!(layout.check_painted_stack(&layout.stack, &sections).is_ok());
+        // the linker script assigned `_stack_start` to the end of RAM itself
+        assert!(layout
+            .check_painted_stack(&(0x2000_0000..0x2001_0000), &sections)
+            .is_err());
+
+        let overlapping = [(".uninit".to_string(), 0x2000_ff00..0x2000_ff10)];
+        assert!(layout
+            .check_painted_stack(&layout.stack, &overlapping)
+            .is_err());
+    }
+}
@@ -1,18 +1,148 @@
 use std::{
     io,
     path::Path,
-    process::{Command, ExitStatus},
+    process::{Command, ExitStatus, Stdio},
 };
 
 pub const LINKER: &str = "rust-lld";
 
+/// Makes LLD report the sections that the linker script does not place ("orphans"), which it
+/// places somewhere on its own otherwise
+const ORPHAN_HANDLING: &str = "--orphan-handling";
+/// Turns LLD's warnings, including the ones about orphans, into errors
+const FATAL_WARNINGS: [&str; 2] = ["--fatal-warnings", "-fatal-warnings"];
+
+/// Name of the linker script that gives the symbols only `flip-link` provides a placeholder value
+pub const PLACEHOLDER_SCRIPT: &str = "flip-link-placeholders.x";
+
 /// Normal linking with just the arguments the user provides
-pub fn link_normally(args: &[String]) -> io::Result<ExitStatus> {
+///
+/// * `placeholder_script` is a linker script from [`placeholder_script`], so that a program that
+///   refers to symbols only `flip-link` provides still links
+///
+/// Returns the exit status, and the output sections LLD put orphan sections into. If `args` set
+/// an orphan handling of their own, or `--fatal-warnings`, LLD's messages about orphans reach the
+/// user unchanged instead.
+pub fn link_normally(
+    args: &[String],
+    placeholder_script: &Path,
+) -> io::Result<(ExitStatus, Vec<String>)> {
     let mut c = Command::new(LINKER);
-    c.args(args);
+    let (flavor, rest) = split_flavor(args);
+    c.args(flavor);
+    let report_orphans = orphan_handling(&mut c, args);
+    c.args(rest).arg(placeholder_script);
     log::trace!("{:?}", c);
 
-    c.status()
+    let (status, orphans, messages) = collect_orphans(c, report_orphans)?;
+    for message in messages {
+        eprintln!("{message}");
+    }
+    Ok((status, orphans))
+}
+
+/// Linker script that defines `symbols` as `0`
+///
+/// Unlike `--defsym`, `PROVIDE` leaves alone the symbols that the program or its linker scripts
+/// define themselves, e.g. `riscv-rt`'s `_heap_size`; the first link has to measure those as they
+/// are.
+pub fn placeholder_script(symbols: &[String]) -> String {
+    symbols
+        .iter()
+        .map(|symbol| format!("PROVIDE({symbol} = 0);\n"))
+        .collect()
+}
+
+/// Links once, with a linker script that already puts the statics at the end of RAM
+///
+/// * `custom_linker_script_dir` is the directory in which the rewritten linker script is located
+/// * `symbols` are defined with `--defsym`, as expressions
+///
+/// There are no placeholders for the other symbols `flip-link` provides: a program that refers to
+/// them fails to link, and needs the two links. Like [`link_normally`], returns the exit status
+/// and the orphan sections. LLD's messages only reach the user if the link succeeds.
+pub fn link_single_pass(
+    args: &[String],
+    current_dir: &Path,
+    custom_linker_script_dir: &Path,
+    symbols: &[(String, String)],
+) -> io::Result<(ExitStatus, Vec<String>)> {
+    let mut c = Command::new(LINKER);
+    let (flavor, rest) = split_flavor(args);
+    c.args(flavor).arg("-L").arg(current_dir);
+    let report_orphans = orphan_handling(&mut c, args);
+    c.args(rest)
+        .args(
+            symbols
+                .iter()
+                .map(|(name, value)| format!("--defsym={name}={value}")),
+        )
+        .current_dir(custom_linker_script_dir);
+    log::trace!("{:?}", c);
+
+    let (status, orphans, messages) = collect_orphans(c, report_orphans)?;
+    if status.success() {
+        for message in messages {
+            eprintln!("{message}");
+        }
+    } else {
+        log::info!("single-pass link failed:\n{}", messages.join("\n"));
+    }
+    Ok((status, orphans))
+}
+
+/// Splits `-flavor gnu`, which has to come first, off `args`; the first part is empty if `args` do
+/// not start with it
+fn split_flavor(args: &[String]) -> (&[String], &[String]) {
+    match args.first().map(String::as_str) {
+        Some("-flavor") if args.len() >= 2 => args.split_at(2),
+        _ => args.split_at(0),
+    }
+}
+
+/// Makes LLD warn about orphan sections, unless `args` set an orphan handling of their own, or
+/// turn warnings into errors; returns whether it does
+fn orphan_handling(c: &mut Command, args: &[String]) -> bool {
+    let report_orphans = reports_orphans(args);
+    if report_orphans {
+        c.arg(format!("{ORPHAN_HANDLING}=warn"));
+    }
+    report_orphans
+}
+
+fn reports_orphans(args: &[String]) -> bool {
+    !args
+        .iter()
+        .any(|arg| arg.starts_with(ORPHAN_HANDLING) || FATAL_WARNINGS.contains(&arg.as_str()))
+}
+
+/// Runs `c` and sorts its messages into the output sections of orphans and everything else
+fn collect_orphans(
+    mut c: Command,
+    report_orphans: bool,
+) -> io::Result<(ExitStatus, Vec<String>, Vec<String>)> {
+    let output = c.stdout(Stdio::inherit()).stderr(Stdio::piped()).output()?;
+    let mut orphans = vec![];
+    let mut messages = vec![];
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        match orphan_output_section(line).filter(|_| report_orphans) {
+            Some(section) if !orphans.iter().any(|orphan| orphan == section) => {
+                orphans.push(section.to_owned())
+            }
+            Some(_) => {}
+            None => messages.push(line.to_owned()),
+        }
+    }
+
+    Ok((output.status, orphans, messages))
+}
+
+/// Parses LLD's `warning: main.o:(.data.x) is being placed in '.data.x'` into the output
+/// section, `.data.x`
+fn orphan_output_section(line: &str) -> Option<&str> {
+    let (_, message) = line.split_once("warning: ")?;
+    let (_, placed) = message.rsplit_once(" is being placed in '")?;
+    placed.strip_suffix('\'')
 }
 
 /// Link using a custom linker script and stack starting point. _(This is the whole point of `flip-link`)_
@@ -20,7 +150,9 @@ pub fn link_normally(args: &[String]) -> io::Result<ExitStatus> {
 /// * `args` are arguments passed to the linker invocation
 /// * `current_dir` is the directory from which the linker was invoked
 /// * `custom_linker_script_dir` is the directory in which the linker script to be used is located
-/// * `stack_start` is the new, custom starting point from which our stack grows downwards –
+/// * `section_starts` are sections to place at fixed addresses with `--section-start`
+/// * `symbols` are defined with `--defsym`; among them `_stack_start`, the new, custom starting
+///   point from which our stack grows downwards –
 ///   this should be right *below* the `.bss+.data` region that we've moved to the top, e.g.:
 ///     ```
 ///      +-------------+
@@ -37,8 +169,8 @@ pub fn link_modified(
     args: &[String],
     current_dir: &Path,
     custom_linker_script_dir: &Path,
-    stack_start: u64,
-    stack_end: u64,
+    section_starts: &[(String, u64)],
+    symbols: &[(String, u64)],
 ) -> io::Result<ExitStatus> {
     let mut c = Command::new(LINKER);
     c
@@ -49,9 +181,18 @@ pub fn link_modified(
         .arg(current_dir)
         // rest of arguments, except `-flavor gnu`
         .args(&args[2..])
-        // we need to override `_stack_start` and `_stack_end` below fake RAM
-        .arg(format!("--defsym=_stack_start={}", stack_start))
-        .arg(format!("--defsym=_stack_end={}", stack_end))
+        // we need to override `_stack_start` and `_stack_end` below fake RAM, and define
+        // whatever else the flipped layout exports
+        .args(
+            symbols
+                .iter()
+                .map(|(name, value)| format!("--defsym={name}={value}")),
+        )
+        .args(
+            section_starts
+                .iter()
+                .map(|(name, address)| format!("--section-start={name}={address:#x}")),
+        )
         // set working directory to temporary directory containing our new linker script
         // this makes sure that it takes precedence over the original one
         .current_dir(custom_linker_script_dir);
@@ -59,3 +200,50 @@ pub fn link_modified(
 
     c.status()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn flavor() {
+        let link = args(&["-flavor", "gnu", "-Tlink.x"]);
+        assert_eq!(split_flavor(&link), (&link[..2], &link[2..]));
+        let version = args(&["--version"]);
+        assert_eq!(split_flavor(&version), (&[][..], &version[..]));
+        assert_eq!(split_flavor(&[]), (&[][..], &[][..]));
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(
+            placeholder_script(&args(&["__eheap", "_heap_size"])),
+            "PROVIDE(__eheap = 0);\nPROVIDE(_heap_size = 0);\n"
+        );
+    }
+
+    #[test]
+    fn orphan_handling_of_the_user() {
+        assert!(reports_orphans(&args(&["-flavor", "gnu", "-Tlink.x"])));
+        assert!(!reports_orphans(&args(&["--orphan-handling=error"])));
+        assert!(!reports_orphans(&args(&["-Tlink.x", "--fatal-warnings"])));
+    }
+
+    #[test]
+    fn orphan_warning() {
+        assert_eq!(
+            orphan_output_section(
+                "rust-lld: warning: app.o:(.ram2.buffer) is being placed in '.ram2.buffer'"
+            ),
+            Some(".ram2.buffer")
+        );
+        assert_eq!(
+            orphan_output_section("rust-lld: warning: unknown -z value: foo"),
+            None
+        );
+    }
+}
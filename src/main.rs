@@ -1,23 +1,40 @@
+mod arch;
 mod argument_parser;
+mod arithmetic;
+mod call_graph;
+mod config;
+mod layout;
 mod linking;
+mod single_pass;
+mod stack_sizes;
+mod stack_usage;
 
 use std::{
     borrow::Cow,
     env,
     fs::{self, File},
     io::{ErrorKind::NotFound, Write},
-    ops::RangeInclusive,
+    iter,
+    ops::{Range, RangeInclusive},
     path::{Path, PathBuf},
     process,
 };
 
-use object::{elf, Object as _, ObjectSection, SectionFlags};
+use arch::Arch;
+use arithmetic::{ErrorKind, LayoutError};
+use config::{Config, LayoutItem};
+use layout::Layout;
+use object::{elf, Object as _, ObjectSection, ObjectSymbol as _, SectionFlags};
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 const EXIT_CODE_FAILURE: i32 = 1;
-/// Stack Pointer alignment required by the ARM architecture
-const SP_ALIGN: u64 = 8;
+/// How many of the largest stack frames to report
+const LARGEST_FRAMES: usize = 10;
+/// Output section that flip-link moves below the main stack without being told to
+const TASK_STACKS_SECTION: &str = ".task_stacks";
+/// Output section that some runtimes and linker scripts reserve the main stack with
+const STACK_SECTION: &str = ".stack";
 
 fn main() -> Result<()> {
     notmain().map(|code| process::exit(code))
@@ -40,32 +57,56 @@ fn notmain() -> Result<i32> {
         return Ok(0);
     }
 
-    {
-        let exit_status = match linking::link_normally(&raw_args) {
-            Ok(status) => status,
-            Err(e) => {
-                if e.kind() == NotFound {
-                    eprintln!(
-                        "flip-link: Could not find the default linker ({}) in your path",
-                        linking::LINKER
-                    );
-                }
-                Err(Box::new(e))
-            }?,
-        };
+    if raw_args[0] == stack_usage::SUBCOMMAND {
+        return stack_usage::run(&raw_args[1..]);
+    }
+
+    let current_dir = env::current_dir()?;
+    let (config, raw_args) = Config::load(&raw_args, &current_dir)?;
 
-        if !exit_status.success() {
-            eprintln!(
-                "\nflip-link: the native linker failed to link the program normally; \
-                please check your project configuration and linker scripts"
-            );
-            return Ok(exit_status.code().unwrap_or(EXIT_CODE_FAILURE));
+    let placeholder_symbols = layout::placeholder_symbols(&config);
+    let single_pass = match config.single_pass {
+        true => link_single_pass(&raw_args, &current_dir)?,
+        false => None,
+    };
+    let (orphans, linked_once) = match single_pass {
+        Some(orphans) => (orphans, true),
+        None => {
+            let first_link = in_tempdir(|tempdir| {
+                let placeholder_script = tempdir.join(linking::PLACEHOLDER_SCRIPT);
+                fs::write(
+                    &placeholder_script,
+                    linking::placeholder_script(&placeholder_symbols),
+                )?;
+                Ok(linking::link_normally(&raw_args, &placeholder_script))
+            })?;
+            let (exit_status, orphans) = match first_link {
+                Ok(output) => output,
+                Err(e) => {
+                    if e.kind() == NotFound {
+                        eprintln!(
+                            "flip-link: Could not find the default linker ({}) in your path",
+                            linking::LINKER
+                        );
+                    }
+                    Err(Box::new(e))
+                }?,
+            };
+
+            if !exit_status.success() {
+                eprintln!(
+                    "\nflip-link: the native linker failed to link the program normally; \
+                    please check your project configuration and linker scripts"
+                );
+                return Ok(exit_status.code().unwrap_or(EXIT_CODE_FAILURE));
+            }
+            // if linking succeeds then linker scripts are well-formed; we'll rely on that in the
+            // parser
+            (orphans, false)
         }
-        // if linking succeeds then linker scripts are well-formed; we'll rely on that in the parser
-    }
+    };
 
     let expanded_args = argument_parser::expand_files(&raw_args);
-    let current_dir = env::current_dir()?;
     let linker_scripts = get_linker_scripts(&expanded_args, &current_dir)?;
 
     // here we assume that we'll end with the same linker script as LLD
@@ -74,7 +115,9 @@ fn notmain() -> Result<i32> {
     let mut ram_path_entry = None;
     for linker_script in linker_scripts {
         let script_contents = fs::read_to_string(linker_script.path())?;
-        if let Some(entry) = find_ram_in_linker_script(&script_contents) {
+        let entry = find_ram_in_linker_script(&script_contents)
+            .map_err(|e| e.in_script(linker_script.path()))?;
+        if let Some(entry) = entry {
             log::info!("found {entry} in {}", linker_script.path().display());
             ram_path_entry = Some((linker_script, entry));
             break;
@@ -86,24 +129,236 @@ fn notmain() -> Result<i32> {
     let output_path = argument_parser::get_output_path(&expanded_args)?;
     let elf = fs::read(output_path)?;
     let object = object::File::parse(elf.as_slice())?;
+    let arch = Arch::from_object(&object);
+
+    if !ram_entry.is_addressable(arch.max_address()) {
+        return Err(format!(
+            "MEMORY.RAM ({ram_entry}) does not fit in the {}-bit address space of the target",
+            arch.address_bits()
+        )
+        .into());
+    }
+
+    let ram_script_contents = fs::read_to_string(ram_linker_script.path())?;
+    let memory_regions = find_memory_entries_in_linker_script(&ram_script_contents);
+
+    // by default the stack goes into RAM, below the statics
+    let (stack_region_name, stack_entry) = match &config.stack_region {
+        Some(name) => {
+            let entry = find_stack_region(
+                "stack",
+                name,
+                &memory_regions,
+                ram_linker_script.path(),
+                &object,
+            )?;
+            (name.as_str(), entry)
+        }
+        None => ("RAM", ram_entry),
+    };
+    let fault_stack_entry = config
+        .fault_stack_region
+        .as_ref()
+        .map(|name| {
+            find_stack_region(
+                "fault stack",
+                name,
+                &memory_regions,
+                ram_linker_script.path(),
+                &object,
+            )
+        })
+        .transpose()?;
+
+    // with the flipped layout a stack overflow only faults if nothing is mapped below the stack;
+    // unless an MPU guard band takes care of that
+    match config.below_stack() {
+        None if config.mpu_guard.is_none() => {
+            if let Some(region) = region_below(stack_entry.origin, &memory_regions) {
+                warn(
+                    &config,
+                    format_args!(
+                        "{stack_region_name} is directly preceded by the {region} memory region; \
+                        a stack overflow will write to {region} instead of faulting (consider \
+                        the `mpu-guard` option)"
+                    ),
+                )?;
+            }
+
+            let peripheral_space = config
+                .peripheral_space
+                .clone()
+                .or_else(|| arch.peripheral_space());
+            if let (Some(below_stack), Some(peripheral_space)) =
+                (stack_entry.origin.checked_sub(1), peripheral_space)
+            {
+                if peripheral_space.contains(&below_stack) {
+                    warn(
+                        &config,
+                        format_args!(
+                            "the memory below {stack_region_name} ({below_stack:#x}) is \
+                            peripheral space; a stack overflow will write to peripheral \
+                            registers instead of faulting"
+                        ),
+                    )?;
+                }
+            }
+        }
+        None | Some(LayoutItem::Guard(_) | LayoutItem::Canary(_)) => {}
+        Some(item) => warn(
+            &config,
+            format_args!(
+                "the {item} lies directly below the stack; a stack overflow will write to it \
+                instead of faulting"
+            ),
+        )?,
+    }
 
-    // TODO assert that `_stack_start == ORIGIN(RAM) + LENGTH(RAM)`
-    // if that's not the case the user has specified a custom location for the stack; we should
-    // error in that case (e.g. the stack may have been placed in CCRAM)
+    // without a guard band or a canary, an overflow of a stack at the bottom of RAM runs through
+    // the reserved bytes before it faults
+    if config.reserved_bottom.is_some()
+        && config.stack_region.is_none()
+        && config.mpu_guard.is_none()
+        && config.canary.is_none()
+    {
+        warn(
+            &config,
+            format_args!(
+                "a stack overflow will overwrite the `reserved-bottom` bytes at the start of RAM \
+                before it faults (consider the `mpu-guard` or `canary` option)"
+            ),
+        )?;
+    }
+
+    // the sections that flip-link moves with `--section-start` are not part of the statics
+    let mut relocated = if config.layout.is_empty() {
+        config
+            .pinned_sections
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .chain(iter::once(TASK_STACKS_SECTION))
+            .chain(config.task_stack_sections.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+    } else {
+        config
+            .layout
+            .iter()
+            .filter_map(|item| match item {
+                LayoutItem::Section(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    };
+    for name in iter::once(STACK_SECTION).chain(config.stack_sections.iter().map(String::as_str)) {
+        if !relocated.contains(&name) {
+            relocated.push(name);
+        }
+    }
+    let stack_section = find_stack_section(&object, ram_entry, &relocated, &config)?;
+
+    check_ram_sections(
+        ram_entry,
+        &memory_regions,
+        &object,
+        &orphans,
+        &relocated,
+        &config,
+    )?;
 
     // compute the span of RAM sections
-    let (used_ram_length, used_ram_align) = compute_span_of_ram_sections(ram_entry, object);
+    let (used_ram_length, used_ram_align) =
+        compute_span_of_ram_sections(ram_entry, &object, &relocated)
+            .map_err(|e| e.in_script(Path::new(output_path)))?;
 
     // the idea is to push `used_ram` all the way to the end of the RAM region
     // to do this we'll use a fake ORIGIN and LENGTH for the RAM region
     // this fake RAM region will be at the end of real RAM region
-    let new_origin = round_down_to_nearest_multiple(
-        ram_entry.end() - used_ram_length,
-        used_ram_align.max(SP_ALIGN),
-    );
-    let new_length = ram_entry.end() - new_origin;
+    let ram_end = ram_entry
+        .end()
+        .map_err(|e| e.in_script(ram_linker_script.path()))?;
+    let mut layout = if config.layout.is_empty() {
+        let regions = layout::Regions {
+            stack: match &config.stack_region {
+                Some(_) => Some(stack_entry.origin..stack_entry.end()?),
+                None => None,
+            },
+            fault_stack: match fault_stack_entry {
+                Some(entry) => Some(entry.origin..entry.end()?),
+                None => None,
+            },
+            task_stacks: find_task_stack_sections(&object, ram_entry, &relocated, &config)?,
+        };
+        let mut layout = Layout::new(
+            ram_entry.origin..ram_end,
+            &regions,
+            used_ram_length,
+            used_ram_align,
+            arch,
+            &config,
+        )?;
+        pin_sections(&mut layout, &object, ram_entry, &relocated, &config)?;
+        layout
+    } else {
+        Layout::from_description(
+            ram_entry.origin..ram_end,
+            &find_layout_sections(&object, ram_entry, &relocated)?,
+            used_ram_length,
+            used_ram_align,
+            arch,
+            &config,
+        )?
+    };
+
+    if let Some((name, size)) = stack_section {
+        layout.place_stack_section(&name, size)?;
+    }
+
+    // `memory.x` files written for `cortex-m-rt`'s `_stack_size` convention state the minimum too
+    let stack_size_symbol = object
+        .symbols()
+        .find(|symbol| symbol.name() == Ok("_stack_size"))
+        .map(|symbol| symbol.address());
+    if let Some(required) = config.min_stack_size.max(stack_size_symbol) {
+        layout.check_stack_size(required, used_ram_length)?;
+    }
+
+    // cortex-m-rt 0.7.4 and later paint `_stack_end.._stack_start` at reset (`paint-stack`); a
+    // runtime that defines `_stack_end` may do so, so the final program gets checked for it
+    let paints_stack = object
+        .symbols()
+        .any(|symbol| symbol.name() == Ok("_stack_end"));
+
+    let frames = stack_sizes::frames(&object)?;
+    report_stack_sizes(&frames, &layout, &config)?;
+    if config.stack_analysis {
+        analyze_stack_usage(&object, &frames, &layout, &config)?;
+    }
+
+    let new_origin = layout.ram.start;
+    let new_length = arithmetic::sub(layout.ram.end, new_origin, "LENGTH(fake RAM)")?;
 
     log::info!("new RAM region: ORIGIN={new_origin:#x}, LENGTH={new_length}");
+    log::info!("flipped layout:\n{layout}");
+    if let Some(stack_limit) = layout.stack_limit {
+        log::info!("ARMv8-M stack limit (`_stack_limit`): {stack_limit:#x}");
+    }
+
+    if let Some(guard) = &layout.guard {
+        if let Some(path) = &config.mpu_guard_file {
+            layout::write_mpu_guard_file(&current_dir.join(path), guard, arch)?;
+        }
+    }
+
+    if linked_once && is_single_pass_layout(&object, &layout) {
+        log::info!("the single-pass link produced the flipped layout");
+        if paints_stack {
+            check_painted_stack(Path::new(output_path), &layout, &config)?;
+        }
+        return Ok(0);
+    }
+    if linked_once {
+        log::info!("the layout needs a second link");
+    }
 
     // to overwrite RAM we'll create a new linker script in a temporary directory
     let exit_status = in_tempdir(|tempdir| {
@@ -127,8 +382,8 @@ fn notmain() -> Result<i32> {
             &raw_args,
             &current_dir,
             tempdir,
-            new_origin,
-            ram_entry.origin,
+            &layout.section_starts(),
+            &layout.symbols(),
         ) {
             Ok(status) => status,
             Err(e) => {
@@ -148,9 +403,270 @@ fn notmain() -> Result<i32> {
         return Ok(exit_status.code().unwrap_or(EXIT_CODE_FAILURE));
     }
 
+    if paints_stack {
+        check_painted_stack(Path::new(output_path), &layout, &config)?;
+    }
+
     Ok(0)
 }
 
+/// Links once with the `SECTIONS` that place the statics rewritten to put them at the end of RAM
+///
+/// Returns the orphan sections, or `None` if the program needs the two links: the linker scripts
+/// place the statics in a way `single_pass::rewrite` does not understand, or the link failed.
+fn link_single_pass(raw_args: &[String], current_dir: &Path) -> Result<Option<Vec<String>>> {
+    let expanded_args = argument_parser::expand_files(raw_args);
+    // there is no output yet; the input objects tell the stack alignment the statics need
+    let Some(arch) = input_architecture(&expanded_args) else {
+        log::info!("no single-pass link: no object file among the linker arguments");
+        return Ok(None);
+    };
+    let linker_scripts = match get_linker_scripts(&expanded_args, current_dir) {
+        Ok(linker_scripts) => linker_scripts,
+        Err(e) => {
+            log::info!("no single-pass link: {e}");
+            return Ok(None);
+        }
+    };
+    let mut rewrites = vec![];
+    for linker_script in linker_scripts {
+        let script_contents = fs::read_to_string(linker_script.path())?;
+        match single_pass::rewrite(&script_contents, arch.sp_align()) {
+            Ok(Some(rewrite)) => rewrites.push((linker_script, rewrite)),
+            Ok(None) => {}
+            Err(e) => {
+                log::info!(
+                    "no single-pass link: {e} in {}",
+                    linker_script.path().display()
+                );
+                return Ok(None);
+            }
+        }
+    }
+    let [(linker_script, rewrite)] = &rewrites[..] else {
+        log::info!(
+            "no single-pass link: {} linker scripts place sections into RAM",
+            rewrites.len()
+        );
+        return Ok(None);
+    };
+    log::info!(
+        "single-pass link with {} at the end of RAM",
+        rewrite.sections.join(", ")
+    );
+
+    in_tempdir(|tempdir| {
+        fs::write(tempdir.join(linker_script.file_name()), &rewrite.script)?;
+        let (exit_status, orphans) =
+            match linking::link_single_pass(raw_args, current_dir, tempdir, &rewrite.symbols()) {
+                Ok(output) => output,
+                Err(e) => {
+                    log::info!("no single-pass link: {e}");
+                    return Ok(None);
+                }
+            };
+        Ok(exit_status.success().then_some(orphans))
+    })
+}
+
+/// Architecture of the first object file among the linker arguments
+fn input_architecture(args: &[String]) -> Option<Arch> {
+    args.iter()
+        .filter(|arg| arg.ends_with(".o"))
+        .find_map(|arg| {
+            let data = fs::read(arg).ok()?;
+            let object = object::File::parse(data.as_slice()).ok()?;
+            Some(Arch::new(object.architecture()))
+        })
+}
+
+/// Is `layout` the one the single-pass link produced in `object`, with nothing to define or move
+/// besides the bounds of the stack?
+fn is_single_pass_layout(object: &object::File<'_>, layout: &Layout) -> bool {
+    let symbols = layout.symbols();
+    layout.section_starts().is_empty()
+        && symbols.len() == 2
+        && symbols.iter().all(|(name, value)| {
+            object
+                .symbols()
+                .any(|symbol| symbol.name() == Ok(name) && symbol.address() == *value)
+        })
+}
+
+/// Checks that painting `_stack_end.._stack_start` at reset covers the flipped stack of the final
+/// program at `path`, and no section
+///
+/// Whether the runtime paints the stack at all is not known, so this only warns.
+fn check_painted_stack(path: &Path, layout: &Layout, config: &Config) -> Result<()> {
+    let elf = fs::read(path)?;
+    let object = object::File::parse(elf.as_slice())?;
+    let symbol = |name: &str| {
+        object
+            .symbols()
+            .find(|symbol| symbol.name() == Ok(name))
+            .map(|symbol| symbol.address())
+            .ok_or_else(|| format!("the final program does not define `{name}`"))
+    };
+    let painted = symbol("_stack_end")?..symbol("_stack_start")?;
+
+    let sections = object
+        .sections()
+        .filter(|section| {
+            matches!(section.flags(), SectionFlags::Elf { sh_flags }
+                if sh_flags & u64::from(elf::SHF_ALLOC) != 0)
+                && section.size() != 0
+        })
+        .map(|section| {
+            let name = section.name().unwrap_or("nameless section").to_string();
+            let end = arithmetic::add(section.address(), section.size(), &name)?;
+            Ok((name, section.address()..end))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    match layout.check_painted_stack(&painted, &sections) {
+        Ok(()) => log::debug!(
+            "painting the stack covers {:#x}..{:#x}",
+            painted.start,
+            painted.end
+        ),
+        Err(e) => warn(config, e)?,
+    }
+    Ok(())
+}
+
+/// Reports the largest stack frames recorded by `-Z emit-stack-sizes`, and warns if a single one of
+/// them does not fit in the stack
+fn report_stack_sizes(
+    frames: &[stack_sizes::Frame],
+    layout: &Layout,
+    config: &Config,
+) -> Result<()> {
+    let Some(largest) = frames.first() else {
+        log::debug!("no .stack_sizes section; compile with `-Z emit-stack-sizes` to get one");
+        return Ok(());
+    };
+
+    log::info!("largest stack frames:");
+    for frame in frames.iter().take(LARGEST_FRAMES) {
+        let name = frame.name.as_deref().unwrap_or("<unknown>");
+        log::info!("  {:>8} bytes  {:#010x}  {name}", frame.size, frame.address);
+    }
+
+    let available = layout.stack.end - layout.stack.start;
+    if largest.size > available {
+        let function = match &largest.name {
+            Some(name) => name.clone(),
+            None => format!("the function at {:#x}", largest.address),
+        };
+        warn(
+            config,
+            format_args!(
+                "the stack frame of {function} ({} bytes) alone is larger than the stack \
+                ({available} bytes)",
+                largest.size,
+            ),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Computes the worst-case stack depth from the call graph and compares it with the stack
+fn analyze_stack_usage(
+    object: &object::File<'_>,
+    frames: &[stack_sizes::Frame],
+    layout: &Layout,
+    config: &Config,
+) -> Result<()> {
+    if frames.is_empty() {
+        return warn(
+            config,
+            "the stack analysis needs the .stack_sizes section; compile with \
+            `-Z emit-stack-sizes` and keep the section in the linker script",
+        );
+    }
+
+    if !call_graph::supports(object.architecture()) {
+        return warn(
+            config,
+            format_args!(
+                "the stack analysis does not support {:?} programs; skipping it",
+                object.architecture()
+            ),
+        );
+    }
+
+    let analysis = call_graph::analyze(object, frames, config)?;
+    log::info!("worst-case stack depth:\n{analysis}");
+
+    // these make the worst case unknowable; say so instead of guessing
+    let list = |names: &std::collections::BTreeSet<String>| {
+        names.iter().cloned().collect::<Vec<_>>().join(", ")
+    };
+    if !analysis.recursive.is_empty() {
+        warn(
+            config,
+            format_args!(
+                "stack analysis: recursion through {}; the worst-case stack depth is unbounded",
+                list(&analysis.recursive)
+            ),
+        )?;
+    }
+    if !analysis.indirect.is_empty() {
+        warn(
+            config,
+            format_args!(
+                "stack analysis: indirect calls (function pointers, trait objects) in {}; their \
+                callees are not included",
+                list(&analysis.indirect)
+            ),
+        )?;
+    }
+    if !analysis.unknown.is_empty() {
+        warn(
+            config,
+            format_args!(
+                "stack analysis: unknown stack frame size of {}; counted as 0 bytes",
+                list(&analysis.unknown)
+            ),
+        )?;
+    }
+
+    let available = layout.stack.end - layout.stack.start;
+    let bound = if analysis.is_bounded() {
+        ""
+    } else {
+        "at least "
+    };
+    if analysis.depth > available {
+        warn(
+            config,
+            format_args!(
+                "the worst-case stack depth is {bound}{} bytes, but the stack only has \
+                {available} bytes",
+                analysis.depth
+            ),
+        )?;
+    } else {
+        log::info!(
+            "worst-case stack depth: {bound}{} of {available} bytes",
+            analysis.depth
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints a warning, or turns it into an error in strict mode
+fn warn(config: &Config, message: impl std::fmt::Display) -> Result<()> {
+    if config.strict {
+        return Err(format!("{message} (this is an error because of the `strict` option)").into());
+    }
+
+    eprintln!("flip-link: warning: {message}");
+    Ok(())
+}
+
 fn in_tempdir<T>(callback: impl FnOnce(&Path) -> Result<T>) -> Result<T> {
     // We avoid the `tempfile` crate because it pulls in quite a few dependencies.
 
@@ -170,26 +686,360 @@ fn in_tempdir<T>(callback: impl FnOnce(&Path) -> Result<T>) -> Result<T> {
     res
 }
 
-/// Returns `(used_ram_length, used_ram_align)`
-fn compute_span_of_ram_sections(ram_entry: MemoryEntry, object: object::File<'_>) -> (u64, u64) {
+/// Finds the `MEMORY` region `name` that the `what` goes into, and checks that no section uses it
+fn find_stack_region(
+    what: &str,
+    name: &str,
+    memory_regions: &[(&str, std::result::Result<MemoryEntry, LayoutError>)],
+    linker_script: &Path,
+    object: &object::File<'_>,
+) -> Result<MemoryEntry> {
+    let entry = memory_regions
+        .iter()
+        .find(|(region, _)| *region == name)
+        .ok_or_else(|| {
+            format!(
+                "{what} region MEMORY.{name} not found in {}",
+                linker_script.display()
+            )
+        })?
+        .1
+        .clone()
+        .map_err(|e| e.in_script(linker_script))?;
+    log::info!("found {what} region {name}: {entry}");
+
+    if let Some(section) = find_section_in_region(object, entry.origin, entry.end()?) {
+        return Err(format!(
+            "the {what} region MEMORY.{name} also contains the {section} section; \
+            the {what} would overwrite it"
+        )
+        .into());
+    }
+
+    Ok(entry)
+}
+
+/// Returns the name of an allocated section that lies (partially) within `start..end`, if any
+fn find_section_in_region(object: &object::File<'_>, start: u64, end: u64) -> Option<String> {
+    object
+        .sections()
+        .filter(|section| {
+            matches!(section.flags(), SectionFlags::Elf { sh_flags }
+                if sh_flags & elf::SHF_ALLOC as u64 != 0)
+        })
+        .find(|section| {
+            let section_start = section.address();
+            let section_end = section_start.saturating_add(section.size());
+            section.size() != 0 && section_start < end && start < section_end
+        })
+        .map(|section| section.name().unwrap_or("nameless").to_string())
+}
+
+/// Places the pinned sections at their offsets below the end of RAM
+fn pin_sections(
+    layout: &mut Layout,
+    object: &object::File<'_>,
+    ram_entry: MemoryEntry,
+    relocated: &[&str],
+    config: &Config,
+) -> Result<()> {
+    let ram_region_span = ram_entry.span()?;
+    for (name, offset) in &config.pinned_sections {
+        let Some(section) = object
+            .section_by_name(name)
+            .filter(|section| is_in_ram(section, &ram_region_span))
+        else {
+            warn(
+                config,
+                format_args!("pinned section {name} is not in RAM, or is empty; ignoring it"),
+            )?;
+            continue;
+        };
+        check_comes_last(object, &section, &ram_region_span, relocated)?;
+
+        layout.pin_section(name, *offset, section.size(), section.align())?;
+    }
+
+    Ok(())
+}
+
+/// The `.task_stacks` section and the `task-stack-sections`, as far as the first link put them
+/// into RAM
+fn find_task_stack_sections(
+    object: &object::File<'_>,
+    ram_entry: MemoryEntry,
+    relocated: &[&str],
+    config: &Config,
+) -> Result<Vec<layout::OutputSection>> {
+    let ram_region_span = ram_entry.span()?;
+    let names = config.task_stack_sections.iter().map(String::as_str);
+    let mut sections = vec![];
+    for name in iter::once(TASK_STACKS_SECTION).chain(names) {
+        if sections
+            .iter()
+            .any(|section: &layout::OutputSection| section.name == name)
+        {
+            continue;
+        }
+        let Some(section) = object
+            .section_by_name(name)
+            .filter(|section| is_in_ram(section, &ram_region_span))
+        else {
+            if name != TASK_STACKS_SECTION {
+                // `.task_stacks` is optional, the sections the user lists are not
+                warn(
+                    config,
+                    format_args!(
+                        "task stack section {name} is not an output section in RAM, or is \
+                        empty; ignoring it (the linker script needs something like \
+                        `{name} (NOLOAD) : {{ *({name} {name}.*) }} > RAM`)"
+                    ),
+                )?;
+            }
+            continue;
+        };
+        check_comes_last(object, &section, &ram_region_span, relocated)?;
+
+        sections.push(layout::OutputSection {
+            name: name.to_owned(),
+            size: section.size(),
+            align: section.align(),
+        });
+    }
+
+    Ok(sections)
+}
+
+/// The output section that reserves the stack, `.stack` or one of the `stack-sections`, and its
+/// size; no size if it extends to the end of RAM, like riscv-rt's `.stack` does
+fn find_stack_section(
+    object: &object::File<'_>,
+    ram_entry: MemoryEntry,
+    relocated: &[&str],
+    config: &Config,
+) -> Result<Option<(String, Option<u64>)>> {
+    let ram_region_span = ram_entry.span()?;
+    let names = iter::once(STACK_SECTION).chain(config.stack_sections.iter().map(String::as_str));
+    let mut found = None;
+    for name in names {
+        // a `.stack` the `layout` places is an ordinary section
+        if config
+            .layout
+            .contains(&LayoutItem::Section(name.to_owned()))
+        {
+            continue;
+        }
+        let Some(section) = object
+            .section_by_name(name)
+            .filter(|section| is_in_ram(section, &ram_region_span))
+        else {
+            continue;
+        };
+        if let Some((other, _)) = &found {
+            return Err(format!("both {other} and {name} reserve the stack; keep only one").into());
+        }
+        check_comes_last(object, &section, &ram_region_span, relocated)?;
+
+        let end = arithmetic::add(section.address(), section.size(), name)?;
+        let size = Some(section.size()).filter(|_| end != *ram_region_span.end());
+        log::debug!("{name} reserves the stack");
+        found = Some((name.to_owned(), size));
+    }
+
+    Ok(found)
+}
+
+/// The output sections that the `layout` names, which the first link has to have put into RAM
+fn find_layout_sections(
+    object: &object::File<'_>,
+    ram_entry: MemoryEntry,
+    relocated: &[&str],
+) -> Result<Vec<layout::OutputSection>> {
+    let ram_region_span = ram_entry.span()?;
+    relocated
+        .iter()
+        .map(|name| {
+            let section = object
+                .section_by_name(name)
+                .filter(|section| is_in_ram(section, &ram_region_span))
+                .ok_or_else(|| {
+                    format!(
+                        "the layout names {name}, but it is not an output section in RAM, or is \
+                        empty"
+                    )
+                })?;
+            check_comes_last(object, &section, &ram_region_span, relocated)?;
+
+            Ok(layout::OutputSection {
+                name: (*name).to_owned(),
+                size: section.size(),
+                align: section.align(),
+            })
+        })
+        .collect()
+}
+
+fn is_in_ram(section: &object::Section<'_, '_>, ram_region_span: &RangeInclusive<u64>) -> bool {
+    section.size() != 0 && ram_region_span.contains(&section.address())
+}
+
+/// Fails unless `section` comes after all RAM sections that stay where they are
+///
+/// `--section-start` moves a section without moving the ones after it, so the sections flip-link
+/// moves (the `relocated` ones) have to come last among the RAM sections of the linker script.
+fn check_comes_last(
+    object: &object::File<'_>,
+    section: &object::Section<'_, '_>,
+    ram_region_span: &RangeInclusive<u64>,
+    relocated: &[&str],
+) -> Result<()> {
+    let name = section.name().unwrap_or("nameless section");
+    if let Some(after) = object.sections().find(|other| {
+        is_in_ram(other, ram_region_span)
+            && other.address() > section.address()
+            && !other.name().is_ok_and(|other| relocated.contains(&other))
+    }) {
+        return Err(format!(
+            "{name} has to come after all other RAM sections in the linker script, but {} \
+            follows it",
+            after.name().unwrap_or("a nameless section")
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Reports the sections in and around RAM that flip-link cannot flip as they are
+///
+/// * a section that straddles the boundary of RAM is an error: only part of it would move
+/// * orphans, the sections that the linker script does not place, go wherever LLD sees fit
+/// * a gap between RAM sections that their alignment does not explain wastes RAM
+fn check_ram_sections(
+    ram_entry: MemoryEntry,
+    memory_regions: &[(&str, std::result::Result<MemoryEntry, LayoutError>)],
+    object: &object::File<'_>,
+    orphans: &[String],
+    relocated: &[&str],
+    config: &Config,
+) -> Result<()> {
+    let ram = ram_entry.origin..ram_entry.end()?;
+    let mut statics = vec![];
+    for section in object.sections() {
+        let SectionFlags::Elf { sh_flags } = section.flags() else {
+            continue;
+        };
+        if sh_flags & u64::from(elf::SHF_ALLOC) == 0 || section.size() == 0 {
+            continue;
+        }
+        let name = section.name().unwrap_or("nameless section");
+        let start = section.address();
+        let end = arithmetic::add(
+            start,
+            section.size(),
+            &format!("address of {name} + its size"),
+        )?;
+
+        let is_inside = ram.start <= start && end <= ram.end;
+        if !is_inside && start < ram.end && ram.start < end {
+            return Err(format!(
+                "{name} ({start:#x}..{end:#x}) straddles the boundary of RAM ({:#x}..{:#x}); \
+                flip-link can only move sections that lie within RAM",
+                ram.start, ram.end
+            )
+            .into());
+        }
+        if is_inside && !relocated.contains(&name) {
+            statics.push((name, start..end, section.align()));
+        }
+
+        let is_writable = sh_flags & u64::from(elf::SHF_WRITE) != 0;
+        if (is_inside || is_writable) && orphans.iter().any(|orphan| orphan == name) {
+            let placement = if is_inside {
+                "into RAM, and flip-link moves it with the statics".to_string()
+            } else {
+                match memory_regions
+                    .iter()
+                    .find(|(_, entry)| entry.as_ref().is_ok_and(|entry| entry.contains(start)))
+                {
+                    Some((region, _)) => format!("into {region}"),
+                    None => "outside of all MEMORY regions".to_string(),
+                }
+            };
+            warn(
+                config,
+                format_args!(
+                    "the linker script does not place {name} ({start:#x}..{end:#x}); the linker \
+                    put it {placement} (assign it to a MEMORY region in the linker script)"
+                ),
+            )?;
+        }
+    }
+
+    for (below, above, gap) in find_gaps(&mut statics) {
+        warn(
+            config,
+            format_args!(
+                "{} bytes of RAM between {below} and {above} ({:#x}..{:#x}) are unused",
+                gap.end - gap.start,
+                gap.start,
+                gap.end
+            ),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Finds the gaps between `sections`, `(name, range, align)`, that the alignment of the section
+/// above the gap does not explain
+fn find_gaps<'a>(
+    sections: &mut [(&'a str, Range<u64>, u64)],
+) -> Vec<(&'a str, &'a str, Range<u64>)> {
+    sections.sort_by_key(|(_, range, _)| range.start);
+
+    let mut gaps = vec![];
+    let mut below: Option<(&str, u64)> = None;
+    for (name, range, align) in sections.iter() {
+        if let Some((below_name, below_end)) = below {
+            let aligned = below_end.checked_next_multiple_of((*align).max(1));
+            if aligned.is_some_and(|aligned| aligned < range.start) {
+                gaps.push((below_name, *name, below_end..range.start));
+            }
+        }
+        if below.is_none_or(|(_, below_end)| range.end > below_end) {
+            below = Some((name, range.end));
+        }
+    }
+
+    gaps
+}
+
+/// Returns `(used_ram_length, used_ram_align)`, leaving out the sections named in `exclude`
+fn compute_span_of_ram_sections(
+    ram_entry: MemoryEntry,
+    object: &object::File<'_>,
+    exclude: &[&str],
+) -> std::result::Result<(u64, u64), LayoutError> {
     let mut used_ram_start = u64::MAX;
     let mut used_ram_end = 0;
     let mut used_ram_align = 0;
-    let ram_region_span = ram_entry.span();
+    let ram_region_span = ram_entry.span()?;
     let mut found_a_section = false;
     for section in object.sections() {
         if let SectionFlags::Elf { sh_flags } = section.flags() {
             if (sh_flags & elf::SHF_ALLOC as u64) != 0 {
+                let name = section.name().unwrap_or("nameless section");
+                if exclude.contains(&name) {
+                    continue;
+                }
                 let start = section.address();
                 let size = section.size();
-                let end = start + size;
+                let end = arithmetic::add(start, size, &format!("address of {name} + its size"))?;
 
                 if ram_region_span.contains(&start) && ram_region_span.contains(&end) {
                     found_a_section = true;
-                    log::debug!(
-                        "{} resides in RAM",
-                        section.name().unwrap_or("nameless section")
-                    );
+                    log::debug!("{name} resides in RAM");
                     used_ram_align = used_ram_align.max(section.align());
 
                     if used_ram_start > start {
@@ -205,19 +1055,16 @@ fn compute_span_of_ram_sections(ram_entry: MemoryEntry, object: object::File<'_>
     }
 
     let used_ram_length = if !found_a_section {
+        log::info!("no allocated section in RAM; the statics get the smallest region possible");
         used_ram_start = ram_entry.origin;
         0
     } else {
-        used_ram_end - used_ram_start
+        arithmetic::sub(used_ram_end, used_ram_start, "end - start of used RAM")?
     };
 
     log::info!("used RAM spans: origin={used_ram_start:#x}, length={used_ram_length}, align={used_ram_align}");
 
-    (used_ram_length, used_ram_align)
-}
-
-fn round_down_to_nearest_multiple(x: u64, multiple: u64) -> u64 {
-    x - (x % multiple)
+    Ok((used_ram_length, used_ram_align))
 }
 
 struct LinkerScript(PathBuf);
@@ -277,12 +1124,23 @@ struct MemoryEntry {
 }
 
 impl MemoryEntry {
-    fn end(&self) -> u64 {
-        self.origin + self.length
+    fn end(&self) -> std::result::Result<u64, LayoutError> {
+        arithmetic::add(self.origin, self.length, "ORIGIN + LENGTH")
+    }
+
+    fn span(&self) -> std::result::Result<RangeInclusive<u64>, LayoutError> {
+        Ok(self.origin..=self.end()?)
+    }
+
+    /// Does `address` lie within the region?
+    fn contains(&self, address: u64) -> bool {
+        self.origin <= address && address - self.origin < self.length
     }
 
-    fn span(&self) -> RangeInclusive<u64> {
-        self.origin..=self.end()
+    /// Does the whole region lie at or below `max_address`?
+    fn is_addressable(&self, max_address: u64) -> bool {
+        self.origin <= max_address
+            && (self.length == 0 || self.length - 1 <= max_address - self.origin)
     }
 }
 
@@ -326,11 +1184,38 @@ fn get_includes_from_linker_script(linker_script: &str) -> Vec<&str> {
 }
 
 /// Looks for "RAM : ORIGIN = $origin, LENGTH = $length"
+///
+/// Errors if the expressions are negative or the region does not fit in the 64-bit address space
+fn find_ram_in_linker_script(
+    linker_script: &str,
+) -> std::result::Result<Option<MemoryEntry>, LayoutError> {
+    find_memory_entries_in_linker_script(linker_script)
+        .into_iter()
+        .find(|(name, _)| *name == "RAM")
+        .map(|(_, entry)| entry)
+        .transpose()
+}
+
+/// Looks for all "$name : ORIGIN = $origin, LENGTH = $length" lines
+///
+/// An entry is an error if its expressions are negative or the region does not fit in the 64-bit
+/// address space
 // FIXME this is a dumb line-by-line parser
-fn find_ram_in_linker_script(linker_script: &str) -> Option<MemoryEntry> {
+fn find_memory_entries_in_linker_script(
+    linker_script: &str,
+) -> Vec<(&str, std::result::Result<MemoryEntry, LayoutError>)> {
+    let mut entries = vec![];
     for (index, mut line) in linker_script.lines().enumerate() {
         line = line.trim();
-        line = eat!(line, "RAM");
+
+        let name_len = line
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(line.len());
+        let name = &line[..name_len];
+        if name.is_empty() {
+            continue;
+        }
+        line = eat!(line, name);
 
         // jump over attributes like (xrw) see parse_attributes()
         if let Some(i) = line.find(':') {
@@ -342,56 +1227,219 @@ fn find_ram_in_linker_script(linker_script: &str) -> Option<MemoryEntry> {
         line = eat!(line, '=');
 
         let boundary_pos = tryc!(line.find(',').ok_or(()));
-        let origin = evaluate_expression(&line[..boundary_pos]) as u64;
+        let origin = &line[..boundary_pos];
         line = line[boundary_pos..].trim();
 
         line = eat!(line, ',');
         line = eat!(line, "LENGTH");
         line = eat!(line, '=');
 
-        let length = evaluate_expression(line) as u64;
-
-        return Some(MemoryEntry {
-            line: index,
-            origin,
-            length,
-        });
+        let entry = (|| {
+            let entry = MemoryEntry {
+                line: index,
+                origin: evaluate_expression(origin)?,
+                length: evaluate_expression(line)?,
+            };
+            entry.end()?;
+            Ok(entry)
+        })();
+
+        entries.push((name, entry));
     }
 
-    None
+    entries
+}
+
+/// Returns the name of the memory region the stack overflows into if it grows below `stack_end`
+///
+/// `regions` come from the `MEMORY` block that also contains RAM
+fn region_below<'a>(
+    stack_end: u64,
+    regions: &[(&'a str, std::result::Result<MemoryEntry, LayoutError>)],
+) -> Option<&'a str> {
+    let below = stack_end.checked_sub(1)?;
+    regions.iter().find_map(|(name, entry)| match entry {
+        Ok(entry) if *name != "RAM" && entry.contains(below) => Some(*name),
+        _ => None,
+    })
 }
 
 /// Evaluate a linker-script expression.
 ///
-/// Panics if the expression is not understood
-fn evaluate_expression(line: &str) -> i64 {
-    log::debug!("Evaluating expression {:?}", line);
+/// Errors if the expression is not understood, overflows or is negative
+fn evaluate_expression(expression: &str) -> std::result::Result<u64, LayoutError> {
+    log::debug!("Evaluating expression {:?}", expression);
 
-    let line = line.replace("K", "*1024");
+    let line = expression.replace("K", "*1024");
     let line = line.replace("M", "*(1024*1024)");
     let line = line.replace("G", "*(1024*1024*1024)");
 
     log::debug!("Now evaluating unpacked expression {:?}", line);
 
+    let error = |kind| LayoutError::new(expression.trim(), kind);
     let value = match evalexpr::eval(&line) {
         Ok(evalexpr::Value::Int(n)) => n,
         Ok(val) => {
-            panic!("Failed to parse expression {line:?} ({line:?}), got {val:?}?");
-        }
-        Err(e) => {
-            panic!("Failed to parse expression {line:?} ({line:?}), got error {e:?}");
+            return Err(error(ErrorKind::Invalid(format!(
+                "expected an integer, got {val:?}"
+            ))));
         }
+        Err(
+            evalexpr::EvalexprError::AdditionError { .. }
+            | evalexpr::EvalexprError::SubtractionError { .. }
+            | evalexpr::EvalexprError::MultiplicationError { .. },
+        ) => return Err(error(ErrorKind::Overflow)),
+        Err(e) => return Err(error(ErrorKind::Invalid(e.to_string()))),
     };
 
     log::debug!("Evaluated expression as {:#x}", value);
 
-    value
+    u64::try_from(value).map_err(|_| error(ErrorKind::Negative(value)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn gaps_between_sections() {
+        let mut sections = [
+            (".uninit", 0x2000_0120..0x2000_0140, 4),
+            (".data", 0x2000_0000..0x2000_0010, 4),
+            // aligned to 32 bytes: no gap
+            (".bss", 0x2000_0020..0x2000_0100, 32),
+        ];
+        assert_eq!(
+            find_gaps(&mut sections),
+            [(".bss", ".uninit", 0x2000_0100..0x2000_0120)]
+        );
+    }
+
+    /// A 32-bit ARM executable with a `NOBITS` section for each `(name, address, size, align)`
+    fn synthetic_elf(sections: &[(&str, u64, u64, u64)]) -> Vec<u8> {
+        const EHSIZE: usize = 52;
+        const SHENTSIZE: usize = 40;
+
+        let mut names = vec![0];
+        let mut name_offsets = vec![];
+        for (name, ..) in sections.iter().chain([&(".shstrtab", 0, 0, 0)]) {
+            name_offsets.push(names.len() as u32);
+            names.extend(name.as_bytes());
+            names.push(0);
+        }
+        let shoff = (EHSIZE + names.len()).next_multiple_of(4);
+        let shnum = sections.len() + 2;
+
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 1, 1, 1];
+        elf.resize(16, 0);
+        for half in [elf::ET_EXEC, elf::EM_ARM] {
+            elf.extend(half.to_le_bytes());
+        }
+        for word in [1, 0, 0, shoff as u32, 0] {
+            elf.extend(u32::to_le_bytes(word));
+        }
+        for half in [EHSIZE, 32, 0, SHENTSIZE, shnum, shnum - 1] {
+            elf.extend((half as u16).to_le_bytes());
+        }
+        elf.extend(&names);
+        elf.resize(shoff, 0);
+
+        elf.resize(shoff + SHENTSIZE, 0);
+        for (&(_, address, size, align), name) in sections.iter().zip(&name_offsets) {
+            let flags = elf::SHF_ALLOC | elf::SHF_WRITE;
+            let header = [
+                *name,
+                elf::SHT_NOBITS,
+                flags,
+                address as u32,
+                0,
+                size as u32,
+            ];
+            for word in header.into_iter().chain([0, 0, align as u32, 0]) {
+                elf.extend(word.to_le_bytes());
+            }
+        }
+        let shstrtab = [
+            name_offsets[sections.len()],
+            elf::SHT_STRTAB,
+            0,
+            0,
+            EHSIZE as u32,
+        ];
+        for word in shstrtab.into_iter().chain([names.len() as u32, 0, 0, 1, 0]) {
+            elf.extend(word.to_le_bytes());
+        }
+        elf
+    }
+
+    #[test]
+    fn span_of_ram_sections() {
+        let ram = MemoryEntry {
+            line: 0,
+            origin: 0x2000_0000,
+            length: 0x1_0000,
+        };
+        let span = |sections: &[(&str, u64, u64, u64)], exclude: &[&str]| {
+            let elf = synthetic_elf(sections);
+            let object = object::File::parse(&*elf).unwrap();
+            compute_span_of_ram_sections(ram, &object, exclude).unwrap()
+        };
+
+        // the padding between sections counts, the sections outside RAM do not
+        let sections = [
+            (".vector_table", 0, 0x400, 4),
+            (".data", 0x2000_0000, 0x5, 4),
+            (".bss", 0x2000_0008, 0x10, 8),
+        ];
+        assert_eq!(span(&sections, &[]), (0x18, 8));
+        assert_eq!(span(&sections, &[".bss"]), (0x5, 4));
+        // nothing in RAM
+        assert_eq!(span(&sections[..1], &[]), (0, 0));
+        // only empty sections in RAM
+        assert_eq!(span(&[(".data", 0x2000_0000, 0, 4)], &[]), (0, 4));
+    }
+
+    #[test]
+    fn empty_ram() {
+        let arm = Arch::new(object::Architecture::Arm);
+        let ram = MemoryEntry {
+            line: 0,
+            origin: 0x2000_0000,
+            length: 0x1_0000,
+        };
+        let layout = |sections: &[(&str, u64, u64, u64)]| {
+            let elf = synthetic_elf(sections);
+            let object = object::File::parse(&*elf).unwrap();
+            let (length, align) = compute_span_of_ram_sections(ram, &object, &[]).unwrap();
+            let regions = layout::Regions::default();
+            let config = Config::default();
+            Layout::new(
+                0x2000_0000..0x2001_0000,
+                &regions,
+                length,
+                align,
+                arm,
+                &config,
+            )
+            .unwrap()
+        };
+
+        // without statics, the fake RAM region still is one alignment unit long
+        let empty = layout(&[(".vector_table", 0, 0x400, 4)]);
+        assert_eq!(empty.ram, 0x2000_fff8..0x2001_0000);
+        assert_eq!(empty.stack, 0x2000_0000..0x2000_fff8);
+        let empty = layout(&[(".data", 0x2000_0000, 0, 4), (".bss", 0x2000_0000, 0, 16)]);
+        assert_eq!(empty.ram, 0x2000_fff0..0x2001_0000);
+
+        // a few bytes of statics round the region up to their alignment
+        let tiny = layout(&[(".data", 0x2000_0000, 1, 1)]);
+        assert_eq!(tiny.ram, 0x2000_fff8..0x2001_0000);
+        let tiny = layout(&[(".data", 0x2000_0000, 1, 1), (".bss", 0x2000_0004, 4, 4)]);
+        assert_eq!(tiny.ram, 0x2000_fff8..0x2001_0000);
+        let tiny = layout(&[(".bss", 0x2000_0000, 9, 4)]);
+        assert_eq!(tiny.ram, 0x2000_fff0..0x2001_0000);
+    }
+
     #[test]
     fn parse() {
         _ = env_logger::try_init();
@@ -405,11 +1453,11 @@ mod tests {
 
         assert_eq!(
             find_ram_in_linker_script(LINKER_SCRIPT),
-            Some(MemoryEntry {
+            Ok(Some(MemoryEntry {
                 line: 3,
                 origin: 0x20000000,
                 length: 64 * 1024,
-            })
+            }))
         );
 
         assert_eq!(
@@ -431,11 +1479,11 @@ mod tests {
 
         assert_eq!(
             find_ram_in_linker_script(LINKER_SCRIPT),
-            Some(MemoryEntry {
+            Ok(Some(MemoryEntry {
                 line: 3,
                 origin: 0x20000000,
                 length: 64 * 1024,
-            })
+            }))
         );
 
         assert_eq!(
@@ -457,11 +1505,11 @@ mod tests {
 
         assert_eq!(
             find_ram_in_linker_script(LINKER_SCRIPT),
-            Some(MemoryEntry {
+            Ok(Some(MemoryEntry {
                 line: 3,
                 origin: 0x20000000,
                 length: 64 * 1024,
-            })
+            }))
         );
 
         assert_eq!(
@@ -474,27 +1522,27 @@ mod tests {
     fn test_perform_addition_hex_and_number() {
         _ = env_logger::try_init();
         const ADDITION: &str = "0x20000000 + 1000";
-        let expected: i64 = 0x20000000 + 1000;
+        let expected: u64 = 0x20000000 + 1000;
 
-        assert_eq!(evaluate_expression(ADDITION), expected);
+        assert_eq!(evaluate_expression(ADDITION), Ok(expected));
     }
 
     #[test]
     fn test_perform_complex_maths() {
         _ = env_logger::try_init();
         const ADDITION: &str = "(0x20000000+1K)-512";
-        let expected: i64 = 0x20000000 + 512;
+        let expected: u64 = 0x20000000 + 512;
 
-        assert_eq!(evaluate_expression(ADDITION), expected);
+        assert_eq!(evaluate_expression(ADDITION), Ok(expected));
     }
 
     #[test]
     fn test_perform_addition_returns_number() {
         _ = env_logger::try_init();
         const NO_ADDITION: &str = "0x20000000";
-        let expected: i64 = 0x20000000;
+        let expected: u64 = 0x20000000;
 
-        assert_eq!(evaluate_expression(NO_ADDITION), expected);
+        assert_eq!(evaluate_expression(NO_ADDITION), Ok(expected));
     }
 
     #[test]
@@ -510,11 +1558,11 @@ mod tests {
 
         assert_eq!(
             find_ram_in_linker_script(LINKER_SCRIPT),
-            Some(MemoryEntry {
+            Ok(Some(MemoryEntry {
                 line: 3,
                 origin: 0x20020000,
                 length: (512 - 256) * 1024,
-            })
+            }))
         );
 
         assert_eq!(
@@ -536,11 +1584,11 @@ mod tests {
 
         assert_eq!(
             find_ram_in_linker_script(LINKER_SCRIPT),
-            Some(MemoryEntry {
+            Ok(Some(MemoryEntry {
                 line: 3,
                 origin: 0x20020000 + (100 * 1024),
                 length: 368 * 1024,
-            })
+            }))
         );
 
         assert_eq!(
@@ -562,11 +1610,11 @@ mod tests {
 
         assert_eq!(
             find_ram_in_linker_script(LINKER_SCRIPT),
-            Some(MemoryEntry {
+            Ok(Some(MemoryEntry {
                 line: 3,
                 origin: 0x20020000 + 1000,
                 length: 368 * 1024,
-            })
+            }))
         );
 
         assert_eq!(
@@ -588,11 +1636,11 @@ mod tests {
 
         assert_eq!(
             find_ram_in_linker_script(LINKER_SCRIPT),
-            Some(MemoryEntry {
+            Ok(Some(MemoryEntry {
                 line: 3,
                 origin: 0x20020000 + (100 * 1024 * 1024),
                 length: 368 * 1024,
-            })
+            }))
         );
 
         assert_eq!(
@@ -614,11 +1662,257 @@ mod tests {
 
         assert_eq!(
             find_ram_in_linker_script(LINKER_SCRIPT),
-            Some(MemoryEntry {
+            Ok(Some(MemoryEntry {
                 line: 4,
                 origin: 0x20000000,
                 length: 128 * 1024,
-            })
+            }))
         );
     }
+
+    #[test]
+    fn parse_msp430() {
+        _ = env_logger::try_init();
+        // from the `msp430-quickstart` template
+        const LINKER_SCRIPT: &str = "MEMORY
+        {
+          RAM : ORIGIN = 0x2000, LENGTH = 0x1000
+          ROM : ORIGIN = 0x8000, LENGTH = 0x7F80
+          VECTORS : ORIGIN = 0xFF80, LENGTH = 0x80
+        }";
+
+        let ram_entry = find_ram_in_linker_script(LINKER_SCRIPT).unwrap().unwrap();
+        assert_eq!(
+            ram_entry,
+            MemoryEntry {
+                line: 2,
+                origin: 0x2000,
+                length: 0x1000,
+            }
+        );
+
+        let msp430 = Arch::new(object::Architecture::Msp430);
+        assert_eq!(msp430.address_bits(), 16);
+        assert_eq!(msp430.sp_align(), 2);
+        assert!(ram_entry.is_addressable(msp430.max_address()));
+        assert!(!msp430
+            .peripheral_space()
+            .unwrap()
+            .contains(&(ram_entry.origin - 1)));
+    }
+
+    #[test]
+    fn msp430_address_space() {
+        let max_address = Arch::new(object::Architecture::Msp430).max_address();
+        let entry = |origin, length| MemoryEntry {
+            line: 0,
+            origin,
+            length,
+        };
+
+        // RAM may end right at the top of the address space ...
+        assert!(entry(0xF000, 0x1000).is_addressable(max_address));
+        assert!(entry(0x0200, 0).is_addressable(max_address));
+        // ... but not beyond it
+        assert!(!entry(0xF000, 0x1001).is_addressable(max_address));
+        assert!(!entry(0x1_0000, 0x100).is_addressable(max_address));
+    }
+
+    #[test]
+    fn peripherals_below_ram() {
+        // e.g. the MSP430G2553 has its 512 bytes of RAM at 0x0200, right above the peripherals
+        let msp430 = Arch::new(object::Architecture::Msp430).peripheral_space();
+        assert_eq!(msp430, Some(0x0000..0x0200));
+        assert!(msp430.unwrap().contains(&0x01FF));
+
+        // ARM leaves it to the vendor
+        assert_eq!(
+            Arch::new(object::Architecture::Arm).peripheral_space(),
+            None
+        );
+    }
+
+    /// Values around the edges of the 32- and 64-bit address spaces
+    const EDGES: [u64; 9] = [
+        0,
+        1,
+        0x7FFF_FFFF,
+        0x8000_0000,
+        0xFFFF_FFFF,
+        0x1_0000_0000,
+        0x7FFF_FFFF_FFFF_FFFE,
+        0x7FFF_FFFF_FFFF_FFFF,
+        0x8000_0000_0000_0000,
+    ];
+
+    /// Deterministic xorshift, so the "fuzz" tests are reproducible
+    fn xorshift(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn ram_ending_at_the_top_of_32_bit_space() {
+        const LINKER_SCRIPT: &str = "MEMORY
+        {
+            RAM : ORIGIN = 0xFFFF0000, LENGTH = 64K
+        }";
+
+        let ram_entry = find_ram_in_linker_script(LINKER_SCRIPT).unwrap().unwrap();
+        assert_eq!(ram_entry.end(), Ok(0xFFFF_FFFF + 1));
+        assert!(ram_entry.span().unwrap().contains(&0xFFFF_FFFF));
+        assert!(!ram_entry.is_addressable(Arch::new(object::Architecture::Arm).max_address() - 1));
+        assert!(ram_entry.is_addressable(Arch::new(object::Architecture::Arm).max_address()));
+    }
+
+    #[test]
+    fn ram_ending_beyond_64_bit_space() {
+        for origin in EDGES {
+            for length in EDGES {
+                let entry = MemoryEntry {
+                    line: 0,
+                    origin,
+                    length,
+                };
+
+                match origin.checked_add(length) {
+                    Some(end) => assert_eq!(entry.end(), Ok(end)),
+                    None => {
+                        let error = entry.end().unwrap_err();
+                        assert_eq!(error.kind, ErrorKind::Overflow);
+                        assert!(entry.span().is_err());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn negative_expressions() {
+        const LINKER_SCRIPT: &str = "MEMORY
+        {
+            RAM : ORIGIN = 0x1000 - 0x2000, LENGTH = 4K
+        }";
+
+        let error = find_ram_in_linker_script(LINKER_SCRIPT).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Negative(-0x1000));
+        assert_eq!(error.expression, "0x1000 - 0x2000");
+
+        let error = error.in_script(Path::new("memory.x"));
+        assert_eq!(
+            error.to_string(),
+            "`0x1000 - 0x2000` evaluates to a negative value (-4096) (in memory.x)"
+        );
+    }
+
+    #[test]
+    fn fuzz_expressions() {
+        _ = env_logger::try_init();
+        let mut state = 0x2545_F491_4F6C_DD1D;
+        for _ in 0..1_000 {
+            // mix edge values with random ones that still fit in an `i64` literal
+            let mut operand = || match xorshift(&mut state) % 3 {
+                0 => EDGES[(xorshift(&mut state) % 8) as usize],
+                _ => xorshift(&mut state) >> (xorshift(&mut state) % 64).max(1),
+            };
+            let (a, b) = (operand(), operand());
+            let (expression, expected) = match xorshift(&mut state) % 3 {
+                0 => (format!("{a:#x} + {b:#x}"), i128::from(a) + i128::from(b)),
+                1 => (format!("{a:#x} - {b:#x}"), i128::from(a) - i128::from(b)),
+                _ => (format!("{a} + {b}K"), i128::from(a) + i128::from(b) * 1024),
+            };
+
+            let result = evaluate_expression(&expression);
+            match i64::try_from(expected) {
+                Ok(value) if value >= 0 => assert_eq!(result, Ok(value as u64), "{expression}"),
+                Ok(value) => assert_eq!(
+                    result.map_err(|e| e.kind),
+                    Err(ErrorKind::Negative(value)),
+                    "{expression}"
+                ),
+                Err(_) => assert_eq!(
+                    result.map_err(|e| e.kind),
+                    Err(ErrorKind::Overflow),
+                    "{expression}"
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn fuzz_memory_entries() {
+        for origin in EDGES.into_iter().filter(|&x| x <= i64::MAX as u64) {
+            for length in EDGES.into_iter().filter(|&x| x <= i64::MAX as u64) {
+                let linker_script = format!(
+                    "MEMORY
+                    {{
+                        RAM : ORIGIN = {origin:#x}, LENGTH = {length:#x}
+                    }}"
+                );
+
+                let ram_entry = find_ram_in_linker_script(&linker_script).unwrap().unwrap();
+                assert_eq!(ram_entry.end(), Ok(origin + length));
+            }
+        }
+    }
+
+    #[test]
+    fn parse_all_regions() {
+        _ = env_logger::try_init();
+        const LINKER_SCRIPT: &str = "MEMORY
+        {
+            FLASH (rx) : ORIGIN = 0x08000000, LENGTH = 1M
+            CCMRAM (rw) : ORIGIN = 0x10000000, LENGTH = 64K
+            RAM2 : ORIGIN = 0x2001C000, LENGTH = 16K
+            RAM : ORIGIN = 0x20000000, LENGTH = 112K
+            BACKUP_SRAM : ORIGIN = 0x40024000, LENGTH = ORIGIN(RAM)
+        }";
+
+        let entries = find_memory_entries_in_linker_script(LINKER_SCRIPT);
+        let names = entries.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        assert_eq!(names, ["FLASH", "CCMRAM", "RAM2", "RAM", "BACKUP_SRAM"]);
+        // expressions we don't understand only make that one entry an error
+        assert!(entries[4].1.is_err());
+
+        // `RAM2` is not `RAM`
+        assert_eq!(
+            find_ram_in_linker_script(LINKER_SCRIPT),
+            Ok(Some(MemoryEntry {
+                line: 5,
+                origin: 0x20000000,
+                length: 112 * 1024,
+            }))
+        );
+    }
+
+    #[test]
+    fn adjacent_regions() {
+        _ = env_logger::try_init();
+        // STM32F4 with SRAM1 and SRAM2 declared separately, and RAM placed in SRAM2
+        const LINKER_SCRIPT: &str = "MEMORY
+        {
+            FLASH : ORIGIN = 0x08000000, LENGTH = 1M
+            SRAM1 : ORIGIN = 0x20000000, LENGTH = 112K
+            RAM : ORIGIN = 0x2001C000, LENGTH = 16K
+        }";
+
+        let regions = find_memory_entries_in_linker_script(LINKER_SCRIPT);
+        assert_eq!(region_below(0x2001_C000, &regions), Some("SRAM1"));
+        // nothing is mapped below SRAM1 itself
+        assert_eq!(region_below(0x2000_0000, &regions), None);
+        assert_eq!(region_below(0, &regions), None);
+    }
+
+    #[test]
+    fn strict_mode() {
+        let config = Config {
+            strict: true,
+            ..Config::default()
+        };
+
+        assert!(warn(&Config::default(), "RAM is directly preceded by SRAM1").is_ok());
+        assert!(warn(&config, "RAM is directly preceded by SRAM1").is_err());
+    }
 }
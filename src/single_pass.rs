@@ -0,0 +1,356 @@
+//! Flipping the layout in a single link
+//!
+//! Instead of linking once to measure the statics and once more to move them, `flip-link` can give
+//! the first output section in RAM the address `ORIGIN(RAM) + LENGTH(RAM) - SIZEOF(..) - ..`, and
+//! let LLD work out where the statics go. The sizes leave out any padding between the sections;
+//! if there is some, the sections overflow RAM, the link fails, and `flip-link` falls back to two
+//! links.
+
+/// Section types that may come between the name of an output section and its `:`
+const SECTION_TYPES: [&str; 6] = ["NOLOAD", "COPY", "INFO", "DSECT", "OVERLAY", "READONLY"];
+
+/// A linker script whose output sections in RAM end at the top of RAM
+#[derive(Debug, PartialEq)]
+pub struct Rewrite {
+    pub script: String,
+    /// Output sections the script places into RAM, in its order
+    pub sections: Vec<String>,
+}
+
+impl Rewrite {
+    /// Symbols to define with `--defsym`; the stack gets all of RAM below the statics
+    pub fn symbols(&self) -> Vec<(String, String)> {
+        vec![
+            (
+                "_stack_start".to_string(),
+                format!("ADDR({})", self.sections[0]),
+            ),
+            ("_stack_end".to_string(), "ORIGIN(RAM)".to_string()),
+        ]
+    }
+}
+
+/// Rewrites the `SECTIONS` of `linker_script` so that the output sections in the `RAM` region end
+/// at the top of RAM
+///
+/// Like in the two-pass layout, the start of the statics is aligned to at least `sp_align`, the
+/// stack alignment of the architecture. Returns `Ok(None)` if the script places no output sections
+/// into RAM, and an error if it does so in a way that is not understood here, e.g. with an address
+/// of its own.
+pub fn rewrite(linker_script: &str, sp_align: u64) -> Result<Option<Rewrite>, String> {
+    let blanked = blank_comments(linker_script);
+    let tokens = tokenize(&blanked);
+
+    let mut sections = vec![];
+    let mut index = 0;
+    while index < tokens.len() {
+        if tokens[index].1 == Token::Word("SECTIONS")
+            && tokens.get(index + 1).map(|t| t.1) == Some(Token::Punct(b'{'))
+        {
+            index = ram_sections(&tokens, index + 2, &mut sections)?;
+        } else {
+            index += 1;
+        }
+    }
+
+    let Some((_, insert_at)) = sections.first() else {
+        return Ok(None);
+    };
+    let names = sections
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect::<Vec<_>>();
+    let size = names
+        .iter()
+        .map(|name| format!("SIZEOF({name})"))
+        .collect::<Vec<_>>()
+        .join(" + ");
+    let align = names.iter().fold(sp_align.to_string(), |align, name| {
+        format!("MAX({align}, ALIGNOF({name}))")
+    });
+
+    let mut script = linker_script.to_string();
+    script.insert_str(
+        *insert_at,
+        &format!(" ((ORIGIN(RAM) + LENGTH(RAM) - ({size})) & ~({align} - 1))"),
+    );
+    Ok(Some(Rewrite {
+        script,
+        sections: names,
+    }))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token<'a> {
+    /// Names, numbers, operators and strings
+    Word(&'a str),
+    /// One of `{}();:,>=`
+    Punct(u8),
+}
+
+/// Collects the output sections in the `RAM` region of the `SECTIONS` command whose body starts at
+/// `tokens[index]`, with the offset right after their name, and returns the index after its body
+fn ram_sections<'a>(
+    tokens: &[(usize, Token<'a>)],
+    mut index: usize,
+    sections: &mut Vec<(&'a str, usize)>,
+) -> Result<usize, String> {
+    let unterminated = || "unterminated SECTIONS command".to_string();
+    loop {
+        // a statement ends with `;`, an output section with its body
+        let start = index;
+        let mut depth = 0_usize;
+        loop {
+            match tokens.get(index).ok_or_else(unterminated)?.1 {
+                Token::Punct(b'(') => depth += 1,
+                Token::Punct(b')') => depth = depth.saturating_sub(1),
+                Token::Punct(b';') if depth == 0 => break,
+                Token::Punct(b'{' | b'}') if depth == 0 => break,
+                _ => {}
+            }
+            index += 1;
+        }
+        match tokens[index].1 {
+            Token::Punct(b';') => index += 1,
+            Token::Punct(b'}') => return Ok(index + 1),
+            _ => {
+                let header = &tokens[start..index];
+                index = skip_body(tokens, index).ok_or_else(unterminated)?;
+
+                // `> RAM`, `AT > FLASH` and `:phdr`, in any order
+                let mut region = None;
+                loop {
+                    match tokens
+                        .get(index..index + 2)
+                        .map(|pair| (pair[0].1, pair[1].1))
+                    {
+                        Some((Token::Punct(b'>'), Token::Word(name))) => {
+                            region = Some(name);
+                            index += 2;
+                        }
+                        Some((Token::Word("AT"), Token::Punct(b'>'))) => index += 3,
+                        Some((Token::Punct(b':'), Token::Word(_))) => index += 2,
+                        _ => break,
+                    }
+                }
+
+                if region == Some("RAM") {
+                    sections.push(output_section_name(header)?);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the index after the body that starts at `tokens[index]`, a `{`
+fn skip_body(tokens: &[(usize, Token<'_>)], mut index: usize) -> Option<usize> {
+    let mut depth = 0_usize;
+    loop {
+        match tokens.get(index)?.1 {
+            Token::Punct(b'{') => depth += 1,
+            Token::Punct(b'}') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index + 1);
+                }
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+}
+
+/// Parses `name [(type)] : ..`, the part of an output section before its body, into the name
+/// and the offset after it
+fn output_section_name<'a>(header: &[(usize, Token<'a>)]) -> Result<(&'a str, usize), String> {
+    let tokens = header.iter().map(|(_, token)| *token).collect::<Vec<_>>();
+    let type_len = match tokens.get(1..4) {
+        Some([Token::Punct(b'('), Token::Word(kind), Token::Punct(b')')])
+            if SECTION_TYPES.contains(kind) =>
+        {
+            3
+        }
+        _ => 0,
+    };
+    match (tokens.first(), tokens.get(1 + type_len)) {
+        (Some(Token::Word(name)), Some(Token::Punct(b':'))) if *name != "OVERLAY" => {
+            Ok((name, header[0].0 + name.len()))
+        }
+        _ => Err(format!(
+            "the RAM output section `{}` has an address, or a form, that is not supported",
+            tokens
+                .iter()
+                .map(|token| match token {
+                    Token::Word(word) => word.to_string(),
+                    Token::Punct(punct) => char::from(*punct).to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        )),
+    }
+}
+
+/// Replaces the comments in `linker_script` with spaces, keeping the offsets of everything else
+fn blank_comments(linker_script: &str) -> String {
+    let mut bytes = linker_script.as_bytes().to_vec();
+    let mut index = 0;
+    while let Some(start) = find(&bytes, index, b"/*") {
+        let end = find(&bytes, start + 2, b"*/").map_or(bytes.len(), |end| end + 2);
+        bytes[start..end].fill(b' ');
+        index = end;
+    }
+    // whole characters were replaced with spaces
+    String::from_utf8(bytes).unwrap()
+}
+
+fn find(bytes: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    bytes
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|position| from + position)
+}
+
+fn tokenize(linker_script: &str) -> Vec<(usize, Token<'_>)> {
+    const PUNCTS: &[u8] = b"{}();:,>=";
+
+    let bytes = linker_script.as_bytes();
+    let mut tokens = vec![];
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index];
+        if byte.is_ascii_whitespace() {
+            index += 1;
+        } else if PUNCTS.contains(&byte) {
+            tokens.push((index, Token::Punct(byte)));
+            index += 1;
+        } else {
+            let start = index;
+            if byte == b'"' {
+                index = find(bytes, index + 1, b"\"").map_or(bytes.len(), |end| end + 1);
+            } else {
+                while index < bytes.len()
+                    && !bytes[index].is_ascii_whitespace()
+                    && !PUNCTS.contains(&bytes[index])
+                    && bytes[index] != b'"'
+                {
+                    index += 1;
+                }
+            }
+            tokens.push((start, Token::Word(&linker_script[start..index])));
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The parts of `cortex-m-rt`'s `link.x` that matter here
+    const LINK_X: &str = "INCLUDE memory.x
+PROVIDE(_stack_start = ORIGIN(RAM) + LENGTH(RAM));
+
+SECTIONS
+{
+  .vector_table ORIGIN(FLASH) :
+  {
+    LONG(_stack_start);
+  } > FLASH
+
+  /* ### .data { } */
+  .data : ALIGN(4)
+  {
+    . = ALIGN(4);
+    __sdata = .;
+    *(.data .data.*);
+    . = ALIGN(4);
+  } > RAM AT>FLASH
+  . = ALIGN(4);
+  __edata = .;
+
+  .bss (NOLOAD) : ALIGN(4)
+  {
+    *(.bss .bss.*);
+  } > RAM
+
+  .uninit (NOLOAD) : ALIGN(4)
+  {
+    *(.uninit .uninit.*);
+  } > RAM
+
+  PROVIDE(__sheap = __euninit);
+
+  /DISCARD/ :
+  {
+    *(.ARM.exidx .ARM.exidx.*);
+  }
+}
+
+ASSERT(ORIGIN(RAM) % 4 == 0, \"ERROR(cortex-m-rt): the start of the RAM region must be 4-byte aligned\");
+";
+
+    #[test]
+    fn cortex_m_rt() {
+        let rewrite = rewrite(LINK_X, 8).unwrap().unwrap();
+        assert_eq!(rewrite.sections, [".data", ".bss", ".uninit"]);
+        assert!(rewrite.script.contains(
+            "  .data ((ORIGIN(RAM) + LENGTH(RAM) - (SIZEOF(.data) + SIZEOF(.bss) + \
+            SIZEOF(.uninit))) & ~(MAX(MAX(MAX(8, ALIGNOF(.data)), ALIGNOF(.bss)), \
+            ALIGNOF(.uninit)) - 1)) : ALIGN(4)\n"
+        ));
+        // the rest of the script stays as it is
+        assert_eq!(
+            rewrite
+                .script
+                .lines()
+                .filter(|line| !line.contains("SIZEOF"))
+                .collect::<Vec<_>>(),
+            LINK_X
+                .lines()
+                .filter(|line| !line.starts_with("  .data :"))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            rewrite.symbols(),
+            [
+                ("_stack_start".to_string(), "ADDR(.data)".to_string()),
+                ("_stack_end".to_string(), "ORIGIN(RAM)".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn stack_alignment() {
+        // RISC-V
+        let rewrite = rewrite(LINK_X, 16).unwrap().unwrap();
+        assert!(rewrite
+            .script
+            .contains("& ~(MAX(MAX(MAX(16, ALIGNOF(.data)),"));
+    }
+
+    #[test]
+    fn no_ram_sections() {
+        assert_eq!(
+            rewrite("MEMORY { RAM : ORIGIN = 0, LENGTH = 1K }", 8),
+            Ok(None)
+        );
+        assert_eq!(
+            rewrite("SECTIONS { .text : { *(.text) } > FLASH /* > RAM */ }", 8),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        // an address of its own
+        assert!(rewrite("SECTIONS { .data 0x20000000 : { *(.data) } > RAM }", 8).is_err());
+        assert!(rewrite("SECTIONS { .bss ALIGN(8) (NOLOAD) : { *(.bss) } > RAM }", 8).is_err());
+        assert!(rewrite(
+            "SECTIONS { OVERLAY : { .a { *(.a) } .b { *(.b) } } > RAM }",
+            8
+        )
+        .is_err());
+        assert!(rewrite("SECTIONS { .data : { *(.data) } > RAM", 8).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use object::{Object as _, ObjectSection as _, ObjectSymbol as _, SymbolKind};
+
+/// Name of the section `-Z emit-stack-sizes` makes rustc (and LLVM) emit
+const SECTION_NAME: &str = ".stack_sizes";
+
+/// Stack frame of a single function
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    pub address: u64,
+    /// Demangled name of the function, if there is a symbol at `address`
+    pub name: Option<String>,
+    /// Size of the stack frame in bytes
+    pub size: u64,
+}
+
+/// Reads the `.stack_sizes` section of `object`, largest frames first
+///
+/// Returns an empty list if the program was not compiled with `-Z emit-stack-sizes`, or if the
+/// linker script did not `KEEP` the section.
+pub fn frames(object: &object::File<'_>) -> Result<Vec<Frame>, String> {
+    let Some(section) = object.section_by_name(SECTION_NAME) else {
+        return Ok(vec![]);
+    };
+    let data = section
+        .data()
+        .map_err(|e| format!("could not read {SECTION_NAME}: {e}"))?;
+
+    // on ARM the address of a Thumb function has bit 0 set, in the symbol table and in
+    // `.stack_sizes` alike; ignore it so that the two always match
+    let thumb = object.architecture() == object::Architecture::Arm;
+    let normalize = |address: u64| if thumb { address & !1 } else { address };
+
+    let mut names = HashMap::new();
+    for symbol in object.symbols() {
+        if symbol.kind() == SymbolKind::Text {
+            if let Ok(name) = symbol.name() {
+                names
+                    .entry(normalize(symbol.address()))
+                    .or_insert_with(|| rustc_demangle::demangle(name).to_string());
+            }
+        }
+    }
+
+    let mut frames = decode(data, object.is_64(), object.is_little_endian())?
+        .into_iter()
+        .map(|(address, size)| Frame {
+            address,
+            name: names.get(&normalize(address)).cloned(),
+            size,
+        })
+        .collect::<Vec<_>>();
+    frames.sort_by(|a, b| b.size.cmp(&a.size).then(a.address.cmp(&b.address)));
+
+    Ok(frames)
+}
+
+/// Decodes `(function address, stack frame size)` pairs
+///
+/// Each entry is the address of the function, as wide as an address of the target, followed by
+/// the size of its stack frame as ULEB128.
+fn decode(mut data: &[u8], is_64: bool, is_little_endian: bool) -> Result<Vec<(u64, u64)>, String> {
+    let address_size = if is_64 { 8 } else { 4 };
+
+    let mut entries = vec![];
+    while !data.is_empty() {
+        if data.len() < address_size {
+            return Err(format!("truncated address in {SECTION_NAME}"));
+        }
+        let (address, rest) = data.split_at(address_size);
+        let mut bytes = [0; 8];
+        let address = if is_little_endian {
+            bytes[..address_size].copy_from_slice(address);
+            u64::from_le_bytes(bytes)
+        } else {
+            bytes[8 - address_size..].copy_from_slice(address);
+            u64::from_be_bytes(bytes)
+        };
+        data = rest;
+
+        let size = read_uleb128(&mut data)
+            .ok_or_else(|| format!("malformed stack size of the function at {address:#x}"))?;
+        entries.push((address, size));
+    }
+
+    Ok(entries)
+}
+
+/// Reads an unsigned LEB128 number from the front of `data` and advances `data` past it
+fn read_uleb128(data: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for (index, byte) in data.iter().enumerate() {
+        let shift = 7 * index as u32;
+        let bits = u64::from(byte & 0x7f);
+        if shift >= u64::BITS || (bits << shift) >> shift != bits {
+            return None; // does not fit in 64 bits
+        }
+        value |= bits << shift;
+
+        if byte & 0x80 == 0 {
+            *data = &data[index + 1..];
+            return Some(value);
+        }
+    }
+
+    None // ran out of bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uleb128() {
+        let mut data: &[u8] = &[0x02, 0xe5, 0x8e, 0x26, 0xff];
+        assert_eq!(read_uleb128(&mut data), Some(2));
+        assert_eq!(read_uleb128(&mut data), Some(624_485));
+        // the last number is missing its final byte
+        assert_eq!(read_uleb128(&mut data), None);
+
+        let mut too_large: &[u8] = &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+        assert_eq!(read_uleb128(&mut too_large), None);
+    }
+
+    #[test]
+    fn decode_entries() {
+        let data = [
+            0x01, 0x01, 0x00, 0x00, 0x10, // 0x101: 16 bytes
+            0x81, 0x02, 0x00, 0x00, 0x80, 0x02, // 0x281: 256 bytes
+        ];
+        assert_eq!(
+            decode(&data, false, true),
+            Ok(vec![(0x101, 16), (0x281, 256)])
+        );
+        assert_eq!(
+            decode(&[0x00, 0x00, 0x01, 0x01, 0x10], false, false),
+            Ok(vec![(0x101, 16)])
+        );
+        assert!(decode(&data[..7], false, true).is_err());
+    }
+}
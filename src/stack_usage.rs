@@ -0,0 +1,302 @@
+//! `flip-link stack-usage`: the stack high-water mark of a painted stack, from a RAM dump
+
+use std::{collections::BTreeMap, fmt, fs, ops::Range, path::Path};
+
+use object::{Object as _, ObjectSymbol as _};
+
+use crate::{Result, EXIT_CODE_FAILURE};
+
+/// Name of the subcommand, as the first argument
+pub const SUBCOMMAND: &str = "stack-usage";
+
+/// Value `cortex-m-rt`'s `paint-stack` feature fills the stack with
+const DEFAULT_PATTERN: u32 = 0xcccc_cccc;
+
+const USAGE: &str =
+    "usage: flip-link stack-usage <ELF> <DUMP> [--base <ADDRESS>] [--pattern <WORD>]
+
+  <ELF>   the program, linked by flip-link
+  <DUMP>  the RAM dump: Intel HEX (.hex, .ihex), or raw binary starting at --base
+  --base <ADDRESS>   address of the first byte of a raw binary dump
+  --pattern <WORD>   32-bit value the stack was painted with (default: 0xcccccccc)";
+
+/// Runs the subcommand; `args` are the arguments after the subcommand name
+pub fn run(args: &[String]) -> Result<i32> {
+    let mut positional = vec![];
+    let mut base = None;
+    let mut pattern = DEFAULT_PATTERN;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            args.next()
+                .ok_or_else(|| format!("{option} needs a value\n\n{USAGE}"))
+                .and_then(|value| crate::evaluate_expression(value).map_err(|e| e.to_string()))
+        };
+        match arg.as_str() {
+            "--base" => base = Some(value("--base")?),
+            "--pattern" => {
+                pattern = u32::try_from(value("--pattern")?)
+                    .map_err(|_| "the pattern must fit in 32 bits")?
+            }
+            "-h" | "--help" => {
+                eprintln!("{USAGE}");
+                return Ok(0);
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let [elf_path, dump_path] = positional[..] else {
+        return Err(USAGE.into());
+    };
+
+    let elf = fs::read(elf_path)?;
+    let object = object::File::parse(elf.as_slice())?;
+    let symbol = |name: &str| {
+        object
+            .symbols()
+            .find(|symbol| symbol.name() == Ok(name))
+            .map(|symbol| symbol.address())
+            .ok_or_else(|| {
+                format!("{elf_path} does not define `{name}`; was it linked by flip-link?")
+            })
+    };
+    let stack = symbol("_stack_end")?..symbol("_stack_start")?;
+
+    let memory = Memory::read(Path::new(dump_path), base)?;
+    let pattern = if object.is_little_endian() {
+        pattern.to_le_bytes()
+    } else {
+        pattern.to_be_bytes()
+    };
+    let usage = StackUsage::new(&memory, stack, pattern)?;
+    println!("{usage}");
+
+    Ok(if usage.reached_bottom() {
+        EXIT_CODE_FAILURE
+    } else {
+        0
+    })
+}
+
+/// Stack high-water mark
+#[derive(Debug, PartialEq)]
+struct StackUsage {
+    /// The stack grows from `stack.end` downwards to `stack.start`
+    stack: Range<u64>,
+    /// Address of the deepest word that no longer holds the paint pattern, if any
+    deepest: Option<u64>,
+}
+
+impl StackUsage {
+    fn new(memory: &Memory, stack: Range<u64>, pattern: [u8; 4]) -> Result<Self> {
+        if stack.start > stack.end {
+            return Err(format!(
+                "the stack ({:#x}..{:#x}) is empty; was it linked by flip-link?",
+                stack.start, stack.end
+            )
+            .into());
+        }
+
+        // scan from the bottom of the stack upwards, for the first word that was overwritten
+        let mut deepest = None;
+        for address in stack.clone().step_by(pattern.len()) {
+            let word = (address..address + pattern.len() as u64)
+                .map(|address| memory.byte(address))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| {
+                    format!("the dump does not contain the stack word at {address:#x}")
+                })?;
+            if word != pattern {
+                deepest = Some(address);
+                break;
+            }
+        }
+
+        Ok(Self { stack, deepest })
+    }
+
+    fn size(&self) -> u64 {
+        self.stack.end - self.stack.start
+    }
+
+    fn peak(&self) -> u64 {
+        self.deepest.map_or(0, |deepest| self.stack.end - deepest)
+    }
+
+    fn reached_bottom(&self) -> bool {
+        self.deepest == Some(self.stack.start)
+    }
+}
+
+impl fmt::Display for StackUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (size, peak) = (self.size(), self.peak());
+        writeln!(
+            f,
+            "stack:      {:#010x}..{:#010x} ({size} bytes)",
+            self.stack.start, self.stack.end
+        )?;
+        writeln!(
+            f,
+            "peak usage: {peak} bytes ({:.1}%)",
+            peak as f64 * 100. / size.max(1) as f64
+        )?;
+        write!(f, "margin:     {} bytes", size - peak)?;
+        if self.reached_bottom() {
+            write!(
+                f,
+                "\nthe stack reached its bottom; the program has probably overflowed it"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Contents of a RAM dump
+#[derive(Debug, Default, PartialEq)]
+struct Memory {
+    /// Contiguous chunks, by start address
+    chunks: BTreeMap<u64, Vec<u8>>,
+}
+
+impl Memory {
+    /// Reads an Intel HEX file, or a raw binary that starts at `base`
+    fn read(path: &Path, base: Option<u64>) -> Result<Self> {
+        let is_hex = matches!(
+            path.extension().and_then(|extension| extension.to_str()),
+            Some("hex" | "ihex")
+        );
+        if is_hex {
+            let contents = fs::read_to_string(path)?;
+            return Ok(Self::parse_hex(&contents).map_err(|e| format!("{}:{e}", path.display()))?);
+        }
+
+        let base = base.ok_or("raw binary dumps need a `--base` address")?;
+        let mut memory = Memory::default();
+        memory.chunks.insert(base, fs::read(path)?);
+        Ok(memory)
+    }
+
+    /// Parses Intel HEX; errors are prefixed with the line number
+    fn parse_hex(contents: &str) -> std::result::Result<Self, String> {
+        let mut memory = Memory::default();
+        // from the extended segment / linear address records
+        let mut upper = 0;
+        for (index, line) in contents.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let bytes = line
+                .strip_prefix(':')
+                .filter(|record| record.is_ascii() && record.len() % 2 == 0)
+                .and_then(|record| {
+                    (0..record.len())
+                        .step_by(2)
+                        .map(|i| u8::from_str_radix(&record[i..i + 2], 16).ok())
+                        .collect::<Option<Vec<_>>>()
+                })
+                .ok_or_else(|| format!("{line_number}: malformed record"))?;
+            let [length, address_high, address_low, kind, ..] = bytes[..] else {
+                return Err(format!("{line_number}: truncated record"));
+            };
+            let data = bytes
+                .get(4..4 + usize::from(length))
+                .filter(|_| bytes.len() == 5 + usize::from(length))
+                .ok_or_else(|| format!("{line_number}: wrong record length"))?;
+            if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+                return Err(format!("{line_number}: wrong checksum"));
+            }
+
+            match (kind, data) {
+                (0x00, _) => {
+                    let address =
+                        upper + u64::from(u16::from_be_bytes([address_high, address_low]));
+                    memory.write(address, data);
+                }
+                (0x01, _) => break,
+                (0x02, &[high, low]) => upper = u64::from(u16::from_be_bytes([high, low])) << 4,
+                (0x04, &[high, low]) => upper = u64::from(u16::from_be_bytes([high, low])) << 16,
+                // start addresses
+                (0x03 | 0x05, _) => {}
+                _ => return Err(format!("{line_number}: unsupported record type {kind:02x}")),
+            }
+        }
+
+        Ok(memory)
+    }
+
+    /// Appends `data` to the chunk that ends at `address`, or starts a new one
+    fn write(&mut self, address: u64, data: &[u8]) {
+        if let Some((start, chunk)) = self.chunks.range_mut(..=address).next_back() {
+            if *start + chunk.len() as u64 == address {
+                chunk.extend_from_slice(data);
+                return;
+            }
+        }
+        self.chunks.insert(address, data.to_vec());
+    }
+
+    fn byte(&self, address: u64) -> Option<u8> {
+        let (start, chunk) = self.chunks.range(..=address).next_back()?;
+        chunk.get(usize::try_from(address - start).ok()?).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATTERN: [u8; 4] = [0xcc; 4];
+
+    fn painted(base: u64, size: usize, used: usize) -> Memory {
+        let mut ram = vec![0xcc; size];
+        ram[size - used..].fill(0x42);
+        let mut memory = Memory::default();
+        memory.write(base, &ram);
+        memory
+    }
+
+    #[test]
+    fn high_water_mark() {
+        let memory = painted(0x2000_0000, 0x100, 0x24);
+        let usage = StackUsage::new(&memory, 0x2000_0000..0x2000_0100, PATTERN).unwrap();
+        assert_eq!(usage.deepest, Some(0x2000_00dc));
+        assert_eq!(usage.peak(), 0x24);
+        assert!(!usage.reached_bottom());
+
+        let memory = painted(0x2000_0000, 0x100, 0x100);
+        let usage = StackUsage::new(&memory, 0x2000_0000..0x2000_0100, PATTERN).unwrap();
+        assert!(usage.reached_bottom());
+
+        let memory = painted(0x2000_0000, 0x100, 0);
+        let usage = StackUsage::new(&memory, 0x2000_0000..0x2000_0100, PATTERN).unwrap();
+        assert_eq!(usage.peak(), 0);
+
+        // the dump ends before the stack does
+        assert!(StackUsage::new(&memory, 0x2000_0000..0x2000_0200, PATTERN).is_err());
+    }
+
+    #[test]
+    fn intel_hex() {
+        const HEX: &str = "\
+:020000042000DA
+:04000000CCCCCCCCCC
+:0400040042424242F0
+:00000001FF
+";
+        let memory = Memory::parse_hex(HEX).unwrap();
+        assert_eq!(memory.chunks.len(), 1);
+        assert_eq!(memory.byte(0x2000_0000), Some(0xcc));
+        assert_eq!(memory.byte(0x2000_0007), Some(0x42));
+        assert_eq!(memory.byte(0x2000_0008), None);
+
+        assert_eq!(
+            Memory::parse_hex(":04000000CCCCCCCCCD"),
+            Err("1: wrong checksum".to_string())
+        );
+        assert!(Memory::parse_hex("CCCC").is_err());
+    }
+}
@@ -4,6 +4,8 @@ use rstest::rstest;
 
 /// Path to test app
 const CRATE: &str = "test-flip-link-app";
+/// Path to the runtime crate, whose examples run in QEMU
+const RT_CRATE: &str = "flip-link-rt";
 /// Example firmware in `$CRATE/examples`
 const FILES: [&str; 4] = ["crash", "exception", "hello", "panic"];
 /// Compilation target firmware is build for
@@ -54,6 +56,62 @@ fn should_verify_memory_layout() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn should_paint_only_the_stack() {
+    // Arrange
+    cargo::check_flip_link();
+    if !qemu::is_installed() {
+        eprintln!("skipping: `qemu-system-arm` is not installed");
+        return;
+    }
+
+    // Act
+    // `cortex-m-rt` paints the stack at reset; the example dumps it and exits QEMU with an error
+    // if painting missed the stack or reached the statics
+    let cmd = cargo::run_rt_example("paint-stack", &[], None);
+
+    // Assert
+    cmd.success();
+}
+
+#[test]
+fn should_classify_a_stack_overflow() {
+    // Arrange
+    cargo::check_flip_link();
+    if !qemu::is_installed() {
+        eprintln!("skipping: `qemu-system-arm` is not installed");
+        return;
+    }
+
+    // Act
+    // the example recurses until the stack overflows; its HardFault handler exits QEMU with an
+    // error unless `fault::classify` reports a stack overflow
+    let cmd = cargo::run_rt_example("overflow", &[], None);
+
+    // Assert
+    let output = String::from_utf8_lossy(&cmd.success().get_output().stdout).into_owned();
+    assert!(output.contains("HardFault: StackOverflow"), "{output}");
+}
+
+#[test]
+fn should_trip_the_canary() {
+    // Arrange
+    cargo::check_flip_link();
+    if !qemu::is_installed() {
+        eprintln!("skipping: `qemu-system-arm` is not installed");
+        return;
+    }
+
+    // Act
+    // the example recurses until `check_canary` fails, and exits QEMU with an error if the
+    // overflow reached the statics
+    let cmd = cargo::run_rt_example("canary", &["canary"], Some("examples/canary.toml"));
+
+    // Assert
+    let output = String::from_utf8_lossy(&cmd.success().get_output().stdout).into_owned();
+    assert!(output.contains("the canary tripped"), "{output}");
+}
+
 mod cargo {
     use std::process::Command;
 
@@ -81,6 +139,23 @@ mod cargo {
             .assert()
     }
 
+    /// Run `example` of `$REPO/$RT_CRATE` in QEMU, with the crate's `features`, and the
+    /// `flip-link` configuration file `config` (relative to `$RT_CRATE`)
+    #[must_use]
+    pub(crate) fn run_rt_example(example: &str, features: &[&str], config: Option<&str>) -> Assert {
+        let mut command = Command::new("cargo");
+        command
+            .args(["run", "--example", example])
+            .current_dir(RT_CRATE);
+        if !features.is_empty() {
+            command.args(["--features", &features.join(",")]);
+        }
+        if let Some(config) = config {
+            command.env("FLIP_LINK_CONFIG", config);
+        }
+        command.unwrap().assert()
+    }
+
     /// Check that `flip-link` is present on the system
     pub(crate) fn check_flip_link() {
         Command::new("which")
@@ -91,6 +166,19 @@ mod cargo {
     }
 }
 
+mod qemu {
+    use std::process::{Command, Stdio};
+
+    /// Is `qemu-system-arm`, which runs the examples of `$RT_CRATE`, present on the system?
+    pub(crate) fn is_installed() -> bool {
+        Command::new("qemu-system-arm")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+}
+
 mod elf {
     use std::{ops::RangeInclusive, path::PathBuf};
 
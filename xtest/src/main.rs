@@ -3,6 +3,10 @@ use std::process::Command;
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 fn main() -> Result<()> {
+    if std::env::args().nth(1).as_deref() == Some("bench") {
+        return bench();
+    }
+
     println!("\n🧹 clean up test artifacts from previous run");
     cargo::clean_test_app()?;
 
@@ -19,6 +23,49 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Compares the time `flip-link` takes to link an example of the test app with two links and
+/// in a single pass
+fn bench() -> Result<()> {
+    const EXAMPLE: &str = "hello";
+    const ROUNDS: u32 = 20;
+
+    // `rustc` only prints the linker arguments if the example gets built again
+    println!("\n🧹 clean up test artifacts from previous run");
+    cargo::clean_test_app()?;
+
+    println!("\n⏳ install latest flip-link");
+    cargo::install_flip_link()?;
+
+    println!("\n⚙️ install target");
+    rustup::install_target()?;
+
+    println!("\n🔗 get the linker arguments of `{EXAMPLE}`");
+    let args = cargo::link_args(EXAMPLE)?;
+
+    for single_pass in [false, true] {
+        let arg = format!("--flip-link-single-pass={single_pass}");
+        let start = std::time::Instant::now();
+        for _ in 0..ROUNDS {
+            // from where `rustc` runs the linker, so that relative paths resolve the same way
+            let status = Command::new("flip-link")
+                .args(&args)
+                .arg(&arg)
+                .current_dir("test-flip-link-app")
+                .status()?;
+            if !status.success() {
+                return Err(format!("linking `{EXAMPLE}` with `{arg}`").into());
+            }
+        }
+        let mode = match single_pass {
+            false => "two links",
+            true => "single pass",
+        };
+        println!("⏱️ {mode}: {:?} per link", start.elapsed() / ROUNDS);
+    }
+
+    Ok(())
+}
+
 mod cargo {
     use super::*;
 
@@ -44,6 +91,45 @@ mod cargo {
         }
     }
 
+    /// Builds `example` of the test app, keeping its object files, and returns the arguments
+    /// `rustc` passes to `flip-link`
+    pub fn link_args(example: &str) -> Result<Vec<String>> {
+        let output = Command::new("cargo")
+            .args(["rustc", "--example", example, "--"])
+            .args(["--print", "link-args", "-C", "save-temps"])
+            .current_dir("test-flip-link-app")
+            .output()?;
+        if !output.status.success() {
+            return Err(format!("building `{example}`").into());
+        }
+
+        // `"flip-link" "-flavor" "gnu" ..`, possibly after some environment variables
+        let stdout = String::from_utf8(output.stdout)?;
+        let line = stdout
+            .lines()
+            .find(|line| line.contains("\"flip-link\""))
+            .ok_or("`rustc` printed no linker arguments")?;
+        let mut args = vec![];
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            if c != '"' {
+                continue;
+            }
+            let mut arg = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => arg.extend(chars.next()),
+                    _ => arg.push(c),
+                }
+            }
+            args.push(arg);
+        }
+
+        let program = args.iter().position(|arg| arg == "flip-link");
+        Ok(args.split_off(program.ok_or("`rustc` does not link with `flip-link`")? + 1))
+    }
+
     pub fn test() -> Result<()> {
         let status = Command::new("cargo")
             // `--test-threads=1` prevents race conditions accessing the elf-file